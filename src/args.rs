@@ -0,0 +1,152 @@
+use remacs::buffer::Backup;
+
+pub const USAGE: &str = "\
+Usage: re [OPTION]... [FILE [+LINE[:COL]]]...
+
+Open each FILE in its own buffer. +LINE[:COL] puts the cursor on that line
+and column of the file before it, or of the next file if it comes first.
+With FILE of -, or no FILE when standard input is not a terminal, read
+standard input into the *stdin* buffer.
+
+Key bindings are read from ~/.remacs, one on each line, as in
+  global-set-key C-c g goto-line
+  global-unset-key C-z
+  define-key isearch C-o isearch-exit
+
+Options:
+  -R, --view     open the files read-only
+      --backup[=CONTROL]
+                 back up each file before first saving it; CONTROL is
+                 simple (file~, the default), numbered (file.~N~) or none
+      --help     show this help and exit
+      --version  show the version and exit
+  --             treat every following argument as a file name";
+
+/// A file named on the command line and where to put the cursor in it.
+#[derive(Debug, PartialEq)]
+pub struct FileArg {
+    pub name: String,
+    /// 1-based line and column.
+    pub position: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    pub files: Vec<FileArg>,
+    /// `-` was given, asking for standard input to be read.
+    pub stdin: bool,
+    pub read_only: bool,
+    pub backup: Backup,
+    pub help: bool,
+    pub version: bool,
+}
+
+/// Parses `+LINE` or `+LINE:COL`.
+fn parse_position(arg: &str) -> Option<(usize, usize)> {
+    let pos = arg.strip_prefix('+')?;
+    let (line, col) = match pos.split_once(':') {
+        Some((line, col)) => (line, col),
+        None => (pos, "1"),
+    };
+    Some((line.parse().ok()?, col.parse().ok()?))
+}
+
+/// Parses the arguments after the program name.
+pub fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut options_done = false;
+    // A position given before any file applies to the next one
+    let mut pending = None;
+
+    for arg in args {
+        if !options_done {
+            match arg.as_str() {
+                "--" => {
+                    options_done = true;
+                    continue;
+                }
+                "-" => {
+                    parsed.stdin = true;
+                    continue;
+                }
+                "-R" | "--view" => {
+                    parsed.read_only = true;
+                    continue;
+                }
+                "--help" => {
+                    parsed.help = true;
+                    continue;
+                }
+                "--version" => {
+                    parsed.version = true;
+                    continue;
+                }
+                "--backup" => {
+                    parsed.backup = Backup::Simple;
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some(control) = arg.strip_prefix("--backup=") {
+                parsed.backup = Backup::from_name(control).ok_or_else(|| format!("invalid backup type '{control}'"))?;
+                continue;
+            }
+            if arg.starts_with('+') {
+                let position = parse_position(arg).ok_or_else(|| format!("invalid position '{arg}'"))?;
+                match parsed.files.last_mut() {
+                    Some(file) if file.position.is_none() => file.position = Some(position),
+                    _ => pending = Some((arg, position)),
+                }
+                continue;
+            }
+            if arg.len() > 1 && arg.starts_with('-') {
+                return Err(format!("unknown option '{arg}'"));
+            }
+        }
+        parsed.files.push(FileArg { name: arg.clone(), position: pending.take().map(|(_, position)| position) });
+    }
+    if let Some((arg, _)) = pending {
+        return Err(format!("no file after '{arg}'"));
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    fn file(name: &str, position: Option<(usize, usize)>) -> FileArg {
+        FileArg { name: name.to_string(), position }
+    }
+
+    #[test]
+    fn positions_apply_to_the_file_before_or_else_after() {
+        let args = parse(&["a", "+3", "+4:5", "b", "c", "+6:7"]).unwrap();
+        assert_eq!(args.files, [file("a", Some((3, 1))), file("b", Some((4, 5))), file("c", Some((6, 7)))]);
+        assert_eq!(parse(&["a", "+x"]), Err("invalid position '+x'".to_string()));
+        assert_eq!(parse(&["a", "+2:"]), Err("invalid position '+2:'".to_string()));
+        assert_eq!(parse(&["a", "+2", "+3"]), Err("no file after '+3'".to_string()));
+    }
+
+    #[test]
+    fn options_are_read_until_a_double_dash() {
+        let args = parse(&["-R", "--backup=numbered", "-", "--", "-R", "+1"]).unwrap();
+        assert!(args.read_only && args.stdin);
+        assert_eq!(args.backup, Backup::Numbered);
+        assert_eq!(args.files, [file("-R", None), file("+1", None)]);
+        assert!(parse(&["--view"]).unwrap().read_only);
+        assert_eq!(parse(&["--backup"]).unwrap().backup, Backup::Simple);
+    }
+
+    #[test]
+    fn unknown_options_are_errors() {
+        assert_eq!(parse(&["-x"]), Err("unknown option '-x'".to_string()));
+        assert_eq!(parse(&["--nope", "a"]), Err("unknown option '--nope'".to_string()));
+        assert_eq!(parse(&["--backup=sometimes"]), Err("invalid backup type 'sometimes'".to_string()));
+    }
+}
@@ -0,0 +1,247 @@
+use crossterm::{
+    cursor,
+    queue,
+    execute,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType},
+};
+
+use std::io::{self, stdout, Stdout, Write};
+
+use crate::screen::Screen;
+
+/// The most unchanged cells rewritten to avoid moving the cursor over them.
+const MAX_REWRITE: usize = 4;
+
+/// Where the editor's screen is shown.
+pub trait Backend {
+    /// Columns and rows available.
+    fn size(&self) -> io::Result<(usize, usize)>;
+
+    /// Makes the display match `screen`.
+    fn draw(&mut self, screen: &Screen) -> io::Result<()>;
+
+    /// Forgets what is on the display, so the next draw repaints all of it.
+    fn invalidate(&mut self);
+
+    /// Takes over the display.
+    fn enter(&mut self) -> io::Result<()>;
+
+    /// Gives the display back, as on exit or while a shell command runs.
+    fn leave(&mut self) -> io::Result<()>;
+}
+
+/// Draws on the terminal through crossterm, in the alternate screen.
+pub struct CrosstermBackend {
+    stdout: Stdout,
+    /// What the terminal shows, if known.
+    shown: Option<Screen>,
+}
+
+impl CrosstermBackend {
+    pub fn new() -> Self {
+        Self { stdout: stdout(), shown: None }
+    }
+}
+
+impl Default for CrosstermBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for CrosstermBackend {
+    fn size(&self) -> io::Result<(usize, usize)> {
+        let (width, height) = terminal::size()?;
+        Ok((width as usize, height as usize))
+    }
+
+    /// Writes only the cells that differ from what the terminal shows,
+    /// in one write.
+    fn draw(&mut self, screen: &Screen) -> io::Result<()> {
+        let mut out = Vec::new();
+        let (shown, cursor) = match self.shown.take() {
+            Some(shown) if shown.width == screen.width && shown.height == screen.height => {
+                let cursor = shown.cursor;
+                (shown, Some(cursor))
+            }
+            _ => {
+                queue!(out, SetAttribute(Attribute::Reset), terminal::Clear(ClearType::All))?;
+                (Screen::new(screen.width, screen.height), None)
+            }
+        };
+        write_changes(&mut out, &shown, screen, cursor)?;
+        self.stdout.write_all(&out)?;
+        self.stdout.flush()?;
+        self.shown = Some(screen.clone());
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    fn enter(&mut self) -> io::Result<()> {
+        terminal::enable_raw_mode()?;
+        execute!(self.stdout, terminal::EnterAlternateScreen, cursor::EnableBlinking, cursor::Show)?;
+        self.invalidate();
+        Ok(())
+    }
+
+    fn leave(&mut self) -> io::Result<()> {
+        execute!(self.stdout, terminal::LeaveAlternateScreen, cursor::Show)?;
+        terminal::disable_raw_mode()
+    }
+}
+
+/// Writes the escape sequences that turn a terminal showing `old`, with its
+/// cursor at `cursor` if that is known, into one showing `new`. Unchanged
+/// cells are skipped, moving the cursor as cheaply as possible, and
+/// attributes are only set when they change.
+fn write_changes(out: &mut impl Write, old: &Screen, new: &Screen, cursor: Option<(usize, usize)>) -> io::Result<()> {
+    // Where the terminal's cursor is, when known. The terminal keeps it
+    // inside the screen whatever it was asked for.
+    let mut at = cursor.filter(|&(x, y)| x < new.width && y < new.height);
+    let mut reverse = false;
+    for y in 0..new.height {
+        let (old_row, row) = (old.row(y), new.row(y));
+        let mut x = 0;
+        while x < new.width {
+            if old_row[x] == row[x] {
+                x += 1;
+                continue;
+            }
+            // A wide character is redrawn from its first column
+            while x > 0 && row[x].symbol.is_empty() {
+                x -= 1;
+            }
+            match at {
+                Some((at_x, at_y)) if at_y == y && at_x == x => {}
+                // Rewriting a few unchanged cells is shorter than moving
+                Some((at_x, at_y))
+                    if at_y == y
+                        && at_x < x
+                        && x - at_x <= MAX_REWRITE
+                        && row[at_x..x].iter().all(|cell| cell.reverse == reverse && cell.symbol.len() == 1) =>
+                {
+                    for cell in &row[at_x..x] {
+                        queue!(out, Print(&cell.symbol))?;
+                    }
+                }
+                Some((_, at_y)) if at_y == y => queue!(out, cursor::MoveToColumn(x as u16))?,
+                _ => queue!(out, cursor::MoveTo(x as u16, y as u16))?,
+            }
+
+            let cell = &row[x];
+            if cell.reverse != reverse {
+                reverse = cell.reverse;
+                let attribute = if reverse { Attribute::Reverse } else { Attribute::NoReverse };
+                queue!(out, SetAttribute(attribute))?;
+            }
+            let symbol = if cell.symbol.is_empty() { " " } else { &cell.symbol };
+            queue!(out, Print(symbol))?;
+            x += 1 + row[x + 1..].iter().take_while(|cell| cell.symbol.is_empty()).count();
+            // Where the cursor goes after the last column varies
+            at = (x < new.width).then_some((x, y));
+        }
+    }
+    if reverse {
+        queue!(out, SetAttribute(Attribute::NoReverse))?;
+    }
+    if at != Some(new.cursor) {
+        let (x, y) = new.cursor;
+        queue!(out, cursor::MoveTo(x as u16, y as u16))?;
+    }
+    Ok(())
+}
+
+/// Shows nothing, for running the editor without a terminal. What would
+/// be drawn is left in the editor's `screen`.
+pub struct MemoryBackend {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl MemoryBackend {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height }
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn size(&self) -> io::Result<(usize, usize)> {
+        Ok((self.width, self.height))
+    }
+
+    fn draw(&mut self, _screen: &Screen) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {}
+
+    fn enter(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn leave(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes(old: &Screen, new: &Screen) -> String {
+        let mut out = Vec::new();
+        write_changes(&mut out, old, new, None).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn only_changed_cells_are_written() {
+        let mut old = Screen::new(10, 2);
+        old.put_str(0, 0, "hello", false);
+        old.put_str(0, 1, "status", true);
+        let mut new = old.clone();
+        new.put_str(1, 0, "a", false);
+        new.cursor = (2, 0);
+        assert_eq!(changes(&old, &new), "\x1b[1;2Ha");
+        assert_eq!(changes(&new, &new), "\x1b[1;3H");
+
+        let mut out = Vec::new();
+        write_changes(&mut out, &new, &new, Some((2, 0))).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn short_gaps_are_rewritten_and_long_ones_skipped() {
+        let old = Screen::new(20, 1);
+        let mut new = old.clone();
+        new.put_str(0, 0, "a", false);
+        new.put_str(3, 0, "b", false);
+        new.put_str(15, 0, "c", false);
+        new.cursor = (16, 0);
+        assert_eq!(changes(&old, &new), "\x1b[1;1Ha  b\x1b[16Gc");
+    }
+
+    #[test]
+    fn attributes_are_set_once_per_run() {
+        let old = Screen::new(6, 2);
+        let mut new = old.clone();
+        new.put_str(0, 0, "ab", true);
+        new.put_str(0, 1, "cd", true);
+        new.cursor = (2, 1);
+        assert_eq!(changes(&old, &new), "\x1b[1;1H\x1b[7mab\x1b[2;1Hcd\x1b[27m");
+    }
+
+    #[test]
+    fn wide_characters_are_redrawn_whole() {
+        let mut old = Screen::new(4, 1);
+        old.put_str(0, 0, "中", false);
+        let mut new = old.clone();
+        new.put_str(0, 0, "中", true);
+        new.cursor = (2, 0);
+        assert_eq!(changes(&old, &new), "\x1b[1;1H\x1b[7m中\x1b[27m");
+    }
+}
@@ -1,48 +1,662 @@
-use std::io::{self, Write};
-use std::fs::{self, File};
-use std::path::Path;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
-pub fn save_buffer(buf: &[String], filename: &str) -> io::Result<usize> {
-    let path = Path::new(filename);
+use crate::diff::{self, Edit};
+use crate::editor::TAB_WIDTH;
+use crate::encoding::FileFormat;
+use crate::text::Text;
+use crate::undo::{Change, UndoLog};
 
-    // Read existing lines from file if it exists
-    let existing_lines: Vec<String> = if path.exists() {
-        match fs::read_to_string(path) {
-            Ok(content) => content.lines().map(|s| s.to_string()).collect(),
-            Err(_) => vec![],
+/// A named piece of text, usually visiting a file.
+pub struct Buffer {
+    pub name: String,
+    pub filename: String,
+    pub filename_given: bool,
+    pub text: Text,
+    /// The encoding and line endings the file was read in and is saved in.
+    pub format: FileFormat,
+    pub modified: bool,
+    pub read_only: bool,
+    /// Columns between tab stops.
+    pub tab_width: usize,
+    /// Whether indenting inserts tab characters rather than spaces.
+    pub indent_tabs: bool,
+    /// Whether long lines wrap at word boundaries instead of scrolling
+    /// sideways.
+    pub visual_line: bool,
+    /// Whether the file was backed up, which happens on the first save only.
+    pub backed_up: bool,
+    /// Whether the auto-save file is up to date with the last edit.
+    pub auto_saved: bool,
+    /// The visited file as it was when last read or written.
+    pub disk_stamp: Option<FileStamp>,
+    pub undo: UndoLog,
+    pub mark: Option<usize>,
+    pub mark_active: bool,
+    /// Point while the buffer is not shown.
+    pub point: usize,
+}
+
+impl Buffer {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            filename: String::new(),
+            filename_given: false,
+            text: Text::new(),
+            format: FileFormat::default(),
+            modified: false,
+            read_only: false,
+            tab_width: TAB_WIDTH,
+            indent_tabs: false,
+            visual_line: false,
+            backed_up: false,
+            auto_saved: true,
+            disk_stamp: None,
+            undo: UndoLog::new(),
+            mark: None,
+            mark_active: false,
+            point: 0,
+        }
+    }
+
+    pub fn from_file(filename: String, text: Text, format: FileFormat) -> Self {
+        Self {
+            name: buffer_name(&filename),
+            disk_stamp: FileStamp::of(&filename),
+            indent_tabs: indents_with_tabs(&filename, &text),
+            filename,
+            filename_given: true,
+            text,
+            format,
+            ..Self::new(String::new())
+        }
+    }
+
+    pub fn new_with_filename(filename: String) -> Self {
+        Self {
+            name: buffer_name(&filename),
+            indent_tabs: indents_with_tabs(&filename, &Text::new()),
+            filename,
+            filename_given: true,
+            ..Self::new(String::new())
+        }
+    }
+
+    /// A buffer holding text that was not read from a file, such as piped
+    /// input. It starts out modified, as the text exists nowhere else.
+    pub fn from_text(name: String, text: Text, format: FileFormat) -> Self {
+        let mut buffer = Self { text, format, modified: true, ..Self::new(name) };
+        buffer.undo.mark_unsaved();
+        buffer
+    }
+
+    /// Inserts `s` at `at` and records it for undo.
+    pub fn insert(&mut self, at: usize, s: &str, point: usize, typing: bool) {
+        let end = at + s.chars().count();
+        self.text.insert(at, s);
+        if let Some(mark) = self.mark.as_mut()
+            && *mark > at
+        {
+            *mark += end - at;
+        }
+        self.mark_active = false;
+        let change = Change::Insert { at, text: s.to_string() };
+        self.undo.record(change, point, end, typing);
+        self.update_modified();
+    }
+
+    /// Removes the chars between `start` and `end`, records it for undo and
+    /// returns them.
+    pub fn delete(&mut self, start: usize, end: usize, point: usize) -> String {
+        let removed = self.text.remove(start..end);
+        if let Some(mark) = self.mark.as_mut() {
+            if *mark >= end {
+                *mark -= end - start;
+            } else if *mark > start {
+                *mark = start;
+            }
+        }
+        self.mark_active = false;
+        let change = Change::Delete { at: start, text: removed.clone() };
+        self.undo.record(change, point, start, false);
+        self.update_modified();
+        removed
+    }
+
+    pub fn update_modified(&mut self) {
+        self.modified = !self.undo.is_saved();
+        self.auto_saved = false;
+    }
+
+    /// Marks the buffer as matching the file on disk.
+    pub fn mark_saved(&mut self) {
+        self.undo.mark_saved();
+        self.modified = false;
+    }
+
+    /// Writes the buffer to its file, backing up the old file if this is the
+    /// first save.
+    pub fn save(&mut self, backup: Backup) -> io::Result<SaveSummary> {
+        let backup = if self.backed_up { Backup::None } else { backup };
+        let summary = save_buffer(&self.text, &self.filename, self.format, backup)?;
+        self.filename_given = true;
+        self.backed_up = true;
+        self.disk_stamp = FileStamp::of(&self.filename);
+        self.mark_saved();
+        self.delete_auto_save();
+        Ok(summary)
+    }
+
+    /// Whether another program changed the visited file since it was last
+    /// read or written here. A file that has gone away does not count.
+    pub fn changed_on_disk(&self) -> bool {
+        let now = FileStamp::of(&self.filename);
+        now.is_some() && now != self.disk_stamp
+    }
+
+    /// Replaces the text with the file's contents, leaving the buffer
+    /// unmodified. The undo history goes with the old text, so that a file
+    /// reverted again and again does not pile up copies of itself.
+    pub fn revert(&mut self) -> io::Result<()> {
+        let (text, format) = load_buffer(&self.filename)?;
+        self.text = text;
+        if let Some(mark) = self.mark.as_mut() {
+            *mark = (*mark).min(self.text.len_chars());
+        }
+        self.mark_active = false;
+        self.undo = UndoLog::new();
+        self.format = format;
+        self.disk_stamp = FileStamp::of(&self.filename);
+        self.update_modified();
+        Ok(())
+    }
+
+    /// The `#file#` next to the visited file that unsaved edits are
+    /// auto-saved to.
+    pub fn auto_save_path(&self) -> Option<PathBuf> {
+        let path = Path::new(&self.filename);
+        let name = path.file_name()?.to_string_lossy();
+        Some(path.with_file_name(format!("#{name}#")))
+    }
+
+    /// Writes the text to the auto-save file if it changed since the last
+    /// auto-save, or removes that file once the buffer is back to matching
+    /// the visited file.
+    pub fn auto_save(&mut self) -> io::Result<()> {
+        if self.auto_saved {
+            return Ok(());
         }
-    } else {
-        vec![]
+        if !self.modified {
+            self.delete_auto_save();
+        } else if let Some(path) = self.auto_save_path() {
+            // Only the user may read it, whoever can read the visited file
+            let mut options = OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            let mut file = options.open(path)?;
+            #[cfg(unix)]
+            file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+            file.write_all(self.text.to_string().as_bytes())?;
+        }
+        self.auto_saved = true;
+        Ok(())
+    }
+
+    pub fn delete_auto_save(&self) {
+        if let Some(path) = self.auto_save_path() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// The contents of the auto-save file, if it is newer than the visited
+    /// file and differs from the buffer.
+    pub fn newer_auto_save(&self) -> Option<String> {
+        let path = self.auto_save_path()?;
+        let saved_at = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        if let Ok(file_at) = fs::metadata(&self.filename).and_then(|m| m.modified())
+            && file_at > saved_at
+        {
+            return None;
+        }
+        let contents = fs::read_to_string(path).ok()?;
+        (contents != self.text.to_string()).then_some(contents)
+    }
+}
+
+/// When a file was last modified and how big it was, to notice changes
+/// made by other programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl FileStamp {
+    pub fn of(filename: &str) -> Option<Self> {
+        let meta = fs::metadata(filename).ok()?;
+        Some(Self { modified: meta.modified().ok()?, len: meta.len() })
+    }
+}
+
+/// Which backup, if any, to keep of a file's previous contents when saving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backup {
+    #[default]
+    None,
+    /// A single `file~`.
+    Simple,
+    /// `file.~1~`, `file.~2~` and so on.
+    Numbered,
+}
+
+impl Backup {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" | "off" => Some(Backup::None),
+            "simple" | "never" => Some(Backup::Simple),
+            "numbered" | "t" => Some(Backup::Numbered),
+            _ => None,
+        }
+    }
+}
+
+/// The buffer name for a file: its last path component.
+pub fn buffer_name(filename: &str) -> String {
+    Path::new(filename)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| filename.to_string())
+}
+
+/// Whether a file should be indented with tabs: Makefiles need them, Go
+/// is formatted with them, and any other file gets them if one of its
+/// lines already starts with one.
+fn indents_with_tabs(filename: &str, text: &Text) -> bool {
+    let name = buffer_name(filename);
+    matches!(name.as_str(), "Makefile" | "makefile" | "GNUmakefile")
+        || name.ends_with(".mk")
+        || name.ends_with(".go")
+        || (0..text.len_lines()).any(|line| text.char(text.line_to_char(line)) == Some('\t'))
+}
+
+/// What a save changed, compared with the file it replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SaveSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub bytes: usize,
+}
+
+impl SaveSummary {
+    /// Counts the lines `new` adds, removes and changes relative to `old`.
+    /// Within each run of edits, deleted lines paired with inserted ones
+    /// count as changed.
+    fn compare(old: &[&str], new: &[&str], bytes: usize) -> Self {
+        let mut summary = Self { bytes, ..Self::default() };
+        let (mut deleted, mut inserted) = (0, 0);
+        for edit in diff::diff(old, new).into_iter().chain([Edit::Keep]) {
+            match edit {
+                Edit::Delete => deleted += 1,
+                Edit::Insert => inserted += 1,
+                Edit::Keep => {
+                    let changed = deleted.min(inserted);
+                    summary.changed += changed;
+                    summary.removed += deleted - changed;
+                    summary.added += inserted - changed;
+                    (deleted, inserted) = (0, 0);
+                }
+            }
+        }
+        summary
+    }
+}
+
+impl fmt::Display for SaveSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} removed, {} changed line{}; {} byte{}",
+            self.added,
+            self.removed,
+            self.changed,
+            if self.changed == 1 { "" } else { "s" },
+            self.bytes,
+            if self.bytes == 1 { "" } else { "s" },
+        )
+    }
+}
+
+/// Writes `buf` to `filename`, whether or not it differs from what is there.
+pub fn save_buffer(buf: &Text, filename: &str, format: FileFormat, backup: Backup) -> io::Result<SaveSummary> {
+    // Write through symlinks rather than replacing them
+    let path = match fs::symlink_metadata(filename) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(filename)?,
+        _ => PathBuf::from(filename),
+    };
+    let path = path.as_path();
+
+    let content = buf.to_string();
+    let bytes = format.encode(&content).map_err(|c| {
+        let encoding = format.encoding;
+        io::Error::new(io::ErrorKind::InvalidData, format!("cannot encode {c:?} in {encoding}"))
+    })?;
+
+    let summary = match fs::read(path) {
+        Ok(existing) => {
+            let existing = FileFormat::decode(&existing).0;
+            let old: Vec<&str> = existing.split('\n').collect();
+            let new: Vec<&str> = content.split('\n').collect();
+            SaveSummary::compare(&old, &new, bytes.len())
+        }
+        Err(_) => SaveSummary { added: buf.len_lines(), bytes: bytes.len(), ..SaveSummary::default() },
     };
 
-    // Compare buffers line by line
-    let mut changed_lines = 0;
-    let max_len = buf.len().max(existing_lines.len());
+    if path.exists() {
+        make_backup(path, backup)?;
+    }
+    if !write_atomic(path, &bytes)? {
+        write_in_place(path, &bytes)?;
+    }
+
+    Ok(summary)
+}
 
-    for i in 0..max_len {
-        let new = buf.get(i).map(|s| s.trim_end());
-        let old = existing_lines.get(i).map(|s| s.trim_end());
+/// Replaces `path` with `bytes` so that a crash leaves either the old or the
+/// new contents, never a mix. The data goes to a temporary file in the same
+/// directory, which is synced and then renamed over `path`. An existing
+/// file's permissions and ownership are kept.
+///
+/// Returns false, having written nothing, when replacing the file would
+/// change it in other ways: when the directory cannot take the temporary
+/// file, the owner cannot be kept, or the file has other hard links.
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<bool> {
+    let meta = fs::metadata(path).ok();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if meta.as_ref().is_some_and(|meta| meta.nlink() > 1) {
+            return Ok(false);
+        }
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let Ok((temp, mut file)) = create_temp(dir, &name) else {
+        return Ok(false);
+    };
 
-        if new != old {
-            changed_lines += 1;
+    let result = (|| {
+        if let Some(meta) = &meta {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                // Only root can give a file away
+                if std::os::unix::fs::fchown(&file, Some(meta.uid()), Some(meta.gid())).is_err() {
+                    return Ok(false);
+                }
+            }
+            file.set_permissions(meta.permissions())?;
         }
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        fs::rename(&temp, path)?;
+        Ok(true)
+    })();
+    if !matches!(result, Ok(true)) {
+        let _ = fs::remove_file(&temp);
+        return result;
     }
 
-    // If no lines changed, skip writing
-    if changed_lines == 0 {
-        return Ok(0);
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
     }
+    Ok(true)
+}
+
+/// Overwrites `path` with `bytes` in the same file, for when it cannot be
+/// replaced. The backup is made first, as a crash can leave the file
+/// half-written.
+fn write_in_place(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
 
-    // Write updated buffer to file
-    let mut file = File::create(path)?;
-    for line in buf {
-        writeln!(file, "{line}")?;
+/// Creates a new, uniquely named file in `dir` to write `name` through.
+fn create_temp(dir: &Path, name: &str) -> io::Result<(PathBuf, File)> {
+    let pid = std::process::id();
+    let mut n = 0;
+    loop {
+        let temp = dir.join(format!(".{name}.{pid}-{n}.tmp"));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
     }
+}
+
+/// Copies `path` to its backup file.
+fn make_backup(path: &Path, backup: Backup) -> io::Result<()> {
+    let name = path.as_os_str().to_string_lossy();
+    let backup_path = match backup {
+        Backup::None => return Ok(()),
+        Backup::Simple => format!("{name}~"),
+        Backup::Numbered => format!("{name}.~{}~", last_backup_number(path) + 1),
+    };
+    fs::copy(path, backup_path)?;
+    Ok(())
+}
+
+/// The highest N among the `file.~N~` backups of `path`, or 0 if none.
+fn last_backup_number(path: &Path) -> usize {
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy().to_string()) else {
+        return 0;
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{name}.~");
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let entry = entry.file_name().to_string_lossy().to_string();
+            entry.strip_prefix(&prefix)?.strip_suffix('~')?.parse().ok()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Reads `filename`, guessing its format.
+pub fn load_buffer(filename: &str) -> io::Result<(Text, FileFormat)> {
+    Ok(decode(&fs::read(filename)?))
+}
 
-    Ok(changed_lines)
+/// Reads all of standard input, guessing its format.
+pub fn load_stdin() -> io::Result<(Text, FileFormat)> {
+    let mut bytes = Vec::new();
+    io::stdin().read_to_end(&mut bytes)?;
+    Ok(decode(&bytes))
 }
 
-pub fn load_buffer(filename: &str) -> io::Result<Vec<String>> {
-    let content = std::fs::read_to_string(filename)?;
-    Ok(content.lines().map(|s| s.to_string()).collect())
+fn decode(bytes: &[u8]) -> (Text, FileFormat) {
+    let (content, format) = FileFormat::decode(bytes);
+    (Text::from(content.as_str()), format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A new, empty directory for one test's files.
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("remacs-{}-{test}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn auto_save_files_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("auto-save");
+        let path = dir.join("secret");
+        fs::write(&path, "key").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let (text, format) = load_buffer(path.to_str().unwrap()).unwrap();
+        let mut buffer = Buffer::from_file(path.to_str().unwrap().to_string(), text, format);
+        buffer.insert(0, "new ", 0, true);
+        buffer.auto_save().unwrap();
+
+        let auto_save = buffer.auto_save_path().unwrap();
+        assert_eq!(fs::read_to_string(&auto_save).unwrap(), "new key");
+        assert_eq!(fs::metadata(&auto_save).unwrap().permissions().mode() & 0o777, 0o600);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Loads `bytes` from a file, checks what the buffer holds, then saves
+    /// it with `x` typed at the start and returns what was written.
+    fn round_trip(test: &str, bytes: &[u8], text: &str, format: &str) -> Vec<u8> {
+        let dir = temp_dir(test);
+        let path = dir.join("file");
+        fs::write(&path, bytes).unwrap();
+        let filename = path.to_str().unwrap().to_string();
+        let (loaded, file_format) = load_buffer(&filename).unwrap();
+        let mut buffer = Buffer::from_file(filename, loaded, file_format);
+        assert_eq!(buffer.text.to_string(), text);
+        assert_eq!(buffer.format.to_string(), format);
+
+        buffer.save(Backup::None).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), bytes, "saved unchanged");
+        buffer.insert(0, "x", 0, true);
+        buffer.save(Backup::None).unwrap();
+        let saved = fs::read(&path).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        saved
+    }
+
+    #[test]
+    fn encodings_survive_loading_and_saving() {
+        let saved = round_trip("utf-8-bom", b"\xEF\xBB\xBFcaf\xC3\xA9\n", "café", "utf-8-bom-unix");
+        assert_eq!(saved, b"\xEF\xBB\xBFxcaf\xC3\xA9\n");
+
+        let saved = round_trip("utf-16le", b"\xFF\xFEh\0\xE9\0\n\0", "hé", "utf-16le-unix");
+        assert_eq!(saved, b"\xFF\xFEx\0h\0\xE9\0\n\0");
+
+        let saved = round_trip("latin-1", b"caf\xE9\n", "café", "latin-1-unix");
+        assert_eq!(saved, b"xcaf\xE9\n");
+    }
+
+    #[test]
+    fn line_endings_survive_loading_and_saving() {
+        let saved = round_trip("crlf", b"one\r\ntwo\r\n", "one\ntwo", "utf-8-dos");
+        assert_eq!(saved, b"xone\r\ntwo\r\n");
+
+        let saved = round_trip("no-final-newline", b"one\ntwo", "one\ntwo", "utf-8-unix");
+        assert_eq!(saved, b"xone\ntwo");
+
+        // Mixed endings load as LF, keeping the odd carriage returns
+        let saved = round_trip("mixed", b"one\r\ntwo\n", "one\r\ntwo", "utf-8-unix");
+        assert_eq!(saved, b"xone\r\ntwo\n");
+    }
+
+    fn save(buffer: &mut Buffer, text: &str, backup: Backup) {
+        let len = buffer.text.len_chars();
+        buffer.delete(0, len, 0);
+        buffer.insert(0, text, 0, false);
+        buffer.save(backup).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_replaces_the_file_keeping_its_permissions() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let dir = temp_dir("atomic");
+        let path = dir.join("file");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        let inode = fs::metadata(&path).unwrap().ino();
+        let mut buffer = Buffer::new_with_filename(path.to_str().unwrap().to_string());
+        save(&mut buffer, "new", Backup::None);
+
+        let meta = fs::metadata(&path).unwrap();
+        assert_ne!(meta.ino(), inode);
+        assert_eq!(meta.permissions().mode() & 0o777, 0o640);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_a_hard_linked_file_writes_it_in_place() {
+        let dir = temp_dir("hard-link");
+        let path = dir.join("file");
+        fs::write(&path, "old").unwrap();
+        fs::hard_link(&path, dir.join("link")).unwrap();
+        let mut buffer = Buffer::new_with_filename(path.to_str().unwrap().to_string());
+        save(&mut buffer, "new", Backup::Simple);
+
+        assert_eq!(fs::read_to_string(dir.join("link")).unwrap(), "new\n");
+        assert_eq!(fs::read_to_string(dir.join("file~")).unwrap(), "old");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn backups_are_made_on_the_first_save_only() {
+        let dir = temp_dir("backup");
+        let path = dir.join("file");
+        let filename = path.to_str().unwrap().to_string();
+        fs::write(&path, "1").unwrap();
+
+        let mut buffer = Buffer::new_with_filename(filename.clone());
+        save(&mut buffer, "2", Backup::Simple);
+        save(&mut buffer, "3", Backup::Simple);
+        assert_eq!(fs::read_to_string(dir.join("file~")).unwrap(), "1");
+
+        let mut buffer = Buffer::new_with_filename(filename.clone());
+        save(&mut buffer, "4", Backup::Numbered);
+        save(&mut buffer, "5", Backup::Numbered);
+        let mut buffer = Buffer::new_with_filename(filename);
+        save(&mut buffer, "6", Backup::Numbered);
+        assert_eq!(fs::read_to_string(dir.join("file.~1~")).unwrap(), "3\n");
+        assert_eq!(fs::read_to_string(dir.join("file.~2~")).unwrap(), "5\n");
+        assert!(!dir.join("file.~3~").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reverting_does_not_grow_the_undo_history() {
+        let dir = temp_dir("revert");
+        let path = dir.join("log");
+        fs::write(&path, "one\n").unwrap();
+        let (text, format) = load_buffer(path.to_str().unwrap()).unwrap();
+        let mut buffer = Buffer::from_file(path.to_str().unwrap().to_string(), text, format);
+        buffer.insert(0, "x", 0, true);
+        for line in ["two", "three", "four"] {
+            let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+            writeln!(file, "{line}").unwrap();
+            buffer.revert().unwrap();
+            assert_eq!(buffer.undo.depth(), 0);
+            assert!(!buffer.modified);
+        }
+        assert_eq!(buffer.text.to_string(), "one\ntwo\nthree\nfour");
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
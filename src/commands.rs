@@ -0,0 +1,344 @@
+//! The commands keys can be bound to, by name.
+
+use std::mem;
+use std::path::Path;
+
+use crate::editor::{Editor, EditorMode};
+use crate::search::{ISearch, Replace};
+
+/// A command that can be bound to keys.
+#[derive(Debug)]
+pub struct Command {
+    pub name: &'static str,
+    run: Run,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Run {
+    Edit(fn(&mut Editor)),
+    /// Returns whether the editor should exit.
+    Exit(fn(&mut Editor) -> bool),
+}
+
+impl Command {
+    /// Runs the command. Returns whether the editor should exit.
+    pub fn run(&self, editor: &mut Editor) -> bool {
+        match self.run {
+            Run::Edit(f) => {
+                f(editor);
+                false
+            }
+            Run::Exit(f) => f(editor),
+        }
+    }
+}
+
+const fn edit(name: &'static str, f: fn(&mut Editor)) -> Command {
+    Command { name, run: Run::Edit(f) }
+}
+
+const fn exit(name: &'static str, f: fn(&mut Editor) -> bool) -> Command {
+    Command { name, run: Run::Exit(f) }
+}
+
+pub static COMMANDS: &[Command] = &[
+    edit("keyboard-quit", Editor::keyboard_quit),
+    edit("set-mark-command", Editor::set_mark),
+    edit("exchange-point-and-mark", Editor::exchange_point_and_mark),
+    edit("move-beginning-of-line", Editor::move_to_line_start),
+    edit("move-end-of-line", Editor::move_to_line_end),
+    edit("forward-char", Editor::move_right),
+    edit("backward-char", Editor::move_left),
+    edit("next-line", Editor::move_next_line),
+    edit("previous-line", Editor::move_prev_line),
+    edit("scroll-up-command", Editor::move_next_page),
+    edit("scroll-down-command", Editor::move_prev_page),
+    edit("scroll-left", |editor| editor.scroll_horizontally(true)),
+    edit("scroll-right", |editor| editor.scroll_horizontally(false)),
+    edit("goto-line", Editor::prompt_goto_line),
+    edit("newline", Editor::insert_newline),
+    edit("indent-for-tab-command", Editor::insert_tab),
+    edit("delete-backward-char", Editor::del_prev_char),
+    edit("delete-char", Editor::del_next_char),
+    edit("kill-word", Editor::del_next_word),
+    edit("backward-kill-word", Editor::del_prev_word),
+    edit("kill-line", Editor::kill_to_eol),
+    edit("kill-region", Editor::kill_region),
+    edit("kill-ring-save", Editor::copy_region),
+    edit("yank", Editor::yank),
+    edit("yank-pop", Editor::yank_pop),
+    edit("undo", Editor::undo),
+    edit("undo-redo", Editor::redo),
+    edit("upcase-region", Editor::upcase_region),
+    edit("downcase-region", Editor::downcase_region),
+    edit("indent-region", Editor::indent_region),
+    edit("isearch-forward", |editor| editor.isearch(true)),
+    edit("isearch-backward", |editor| editor.isearch(false)),
+    edit("query-replace", |editor| editor.query_replace(false)),
+    edit("query-replace-regexp", |editor| editor.query_replace(true)),
+    edit("save-buffer", Editor::save_file),
+    edit("write-file", Editor::write_buffer),
+    edit("find-file", Editor::prompt_find_file),
+    edit("find-file-other-window", Editor::prompt_find_file_other_window),
+    edit("revert-buffer", Editor::prompt_revert_buffer),
+    edit("list-buffers", Editor::list_buffers),
+    edit("switch-to-buffer", Editor::prompt_switch_buffer),
+    edit("switch-to-buffer-other-window", Editor::prompt_switch_buffer_other_window),
+    edit("kill-buffer", Editor::prompt_kill_buffer),
+    edit("read-only-mode", Editor::toggle_read_only),
+    edit("set-buffer-file-coding-system", Editor::prompt_set_encoding),
+    edit("set-tab-width", Editor::prompt_set_tab_width),
+    edit("indent-tabs-mode", Editor::toggle_indent_tabs),
+    edit("visual-line-mode", Editor::toggle_visual_line),
+    edit("transient-mark-mode", Editor::toggle_transient_mark),
+    edit("split-window-below", |editor| editor.split_window(false)),
+    edit("split-window-right", |editor| editor.split_window(true)),
+    edit("delete-window", Editor::delete_window),
+    edit("delete-other-windows", Editor::delete_other_windows),
+    edit("other-window", Editor::other_window),
+    edit("shell-command", Editor::write_shell_cmd),
+    exit("save-buffers-kill-terminal", Editor::quit),
+    exit("quick-exit", Editor::quick_exit),
+    // Incremental search
+    edit("isearch-repeat-forward", |editor| isearch_repeat(editor, true)),
+    edit("isearch-repeat-backward", |editor| isearch_repeat(editor, false)),
+    edit("isearch-ring-retreat", |editor| isearch_ring(editor, true)),
+    edit("isearch-ring-advance", |editor| isearch_ring(editor, false)),
+    edit("isearch-delete-char", isearch_delete_char),
+    edit("isearch-exit", isearch_exit),
+    edit("isearch-abort", isearch_abort),
+    // Query replace
+    edit("replace-act", replace_act),
+    edit("replace-skip", replace_skip),
+    edit("replace-act-and-exit", replace_act_and_exit),
+    edit("replace-automatic", replace_automatic),
+    edit("replace-exit", replace_exit),
+    // Prompts
+    edit("exit-minibuffer", exit_minibuffer),
+    edit("minibuffer-complete", minibuffer_complete),
+    edit("minibuffer-delete-backward-char", minibuffer_delete_backward_char),
+    edit("minibuffer-keyboard-quit", minibuffer_keyboard_quit),
+    exit("answer-yes", answer_yes),
+    edit("answer-no", answer_no),
+];
+
+/// The command called `name`.
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+fn take_isearch(editor: &mut Editor) -> Option<ISearch> {
+    match mem::replace(&mut editor.mode, EditorMode::Normal) {
+        EditorMode::ISearch(search) => Some(search),
+        mode => {
+            editor.mode = mode;
+            None
+        }
+    }
+}
+
+/// Runs `f` on the incremental search in progress, then moves point to its
+/// match.
+fn with_isearch(editor: &mut Editor, f: impl FnOnce(&mut Editor, &mut ISearch)) {
+    let Some(mut search) = take_isearch(editor) else {
+        return;
+    };
+    f(editor, &mut search);
+    editor.set_point(search.point());
+    editor.mode = EditorMode::ISearch(search);
+}
+
+fn isearch_repeat(editor: &mut Editor, forward: bool) {
+    with_isearch(editor, |editor, search| {
+        if search.query.is_empty() {
+            // Search again for the previous query
+            if let Some(last) = editor.search_history.last() {
+                search.query = last.clone();
+                search.forward = forward;
+                search.research(editor.text());
+            }
+        } else if search.forward != forward {
+            search.forward = forward;
+            search.failing = false;
+            search.next(editor.text());
+        } else {
+            search.next(editor.text());
+        }
+    });
+}
+
+/// Searches for an earlier query from the history, or a later one.
+fn isearch_ring(editor: &mut Editor, back: bool) {
+    with_isearch(editor, |editor, search| {
+        let len = editor.search_history.len();
+        if len == 0 {
+            return;
+        }
+        let idx = match (search.history_idx, back) {
+            (None, true) => len - 1,
+            (None, false) => 0,
+            (Some(i), true) => i.saturating_sub(1),
+            (Some(i), false) => (i + 1).min(len - 1),
+        };
+        search.history_idx = Some(idx);
+        search.query = editor.search_history[idx].clone();
+        search.found = None;
+        search.research(editor.text());
+    });
+}
+
+/// Adds `c` to the query of the incremental search in progress.
+pub fn isearch_printing_char(editor: &mut Editor, c: char) {
+    with_isearch(editor, |editor, search| {
+        search.query.push(c);
+        search.research(editor.text());
+    });
+}
+
+fn isearch_delete_char(editor: &mut Editor) {
+    with_isearch(editor, |editor, search| {
+        search.query.pop();
+        search.found = None;
+        search.wrapped = false;
+        search.research(editor.text());
+    });
+}
+
+/// Ends the incremental search in progress, leaving point at the match.
+pub fn isearch_exit(editor: &mut Editor) {
+    if let Some(search) = take_isearch(editor) {
+        editor.isearch_done(search);
+    }
+}
+
+fn isearch_abort(editor: &mut Editor) {
+    if let Some(search) = take_isearch(editor) {
+        editor.set_point(search.origin);
+    }
+}
+
+fn take_replace(editor: &mut Editor) -> Option<Box<Replace>> {
+    match mem::replace(&mut editor.mode, EditorMode::Normal) {
+        EditorMode::QueryReplace(replace) => Some(replace),
+        mode => {
+            editor.mode = mode;
+            None
+        }
+    }
+}
+
+fn replace_act(editor: &mut Editor) {
+    if let Some(mut replace) = take_replace(editor) {
+        editor.replace_match(&mut replace);
+        editor.replace_next(replace);
+    }
+}
+
+fn replace_skip(editor: &mut Editor) {
+    if let Some(replace) = take_replace(editor) {
+        editor.replace_next(replace);
+    }
+}
+
+/// Replaces this match and stops.
+fn replace_act_and_exit(editor: &mut Editor) {
+    if let Some(mut replace) = take_replace(editor) {
+        editor.replace_match(&mut replace);
+        editor.replace_done(&replace);
+    }
+}
+
+/// Replaces all remaining matches without asking.
+fn replace_automatic(editor: &mut Editor) {
+    if let Some(mut replace) = take_replace(editor) {
+        while replace.found.is_some() {
+            editor.replace_match(&mut replace);
+            replace.next_match();
+        }
+        editor.replace_done(&replace);
+    }
+}
+
+pub fn replace_exit(editor: &mut Editor) {
+    if let Some(replace) = take_replace(editor) {
+        editor.replace_done(&replace);
+    }
+}
+
+/// Acts on the text entered at a prompt.
+fn exit_minibuffer(editor: &mut Editor) {
+    match mem::replace(&mut editor.mode, EditorMode::Normal) {
+        // Nothing has been entered, so continue to wait for a filename
+        EditorMode::SaveFile(filename) if filename.is_empty() => editor.mode = EditorMode::SaveFile(filename),
+        EditorMode::SaveFile(filename) if filename != editor.buffer().filename && Path::new(&filename).exists() => {
+            editor.mode = EditorMode::ConfirmOverwrite(filename);
+        }
+        EditorMode::SaveFile(filename) => editor.write_file(filename),
+        EditorMode::SwitchBuffer(name) => editor.switch_buffer_by_name(&name),
+        EditorMode::FindFile(filename) => {
+            if !filename.is_empty() && editor.find_file(&filename) {
+                editor.offer_recovery(editor.cur_buf);
+            }
+        }
+        EditorMode::KillBuffer(name) => editor.kill_buffer_by_name(&name),
+        EditorMode::SetEncoding(name) => editor.set_encoding(&name),
+        EditorMode::SetTabWidth(width) => editor.set_tab_width(&width),
+        EditorMode::GotoLine(line) => editor.goto_line_number(&line),
+        EditorMode::ShellCommand(command) => {
+            let _ = editor.run_shell_cmd(&command);
+        }
+        EditorMode::ReplaceFrom { regexp, from } if from.is_empty() => {
+            editor.mode = EditorMode::ReplaceFrom { regexp, from };
+        }
+        EditorMode::ReplaceFrom { regexp, from } => {
+            editor.mode = EditorMode::ReplaceTo { regexp, from, to: String::new() };
+        }
+        EditorMode::ReplaceTo { regexp, from, to } => editor.start_replace(from, to, regexp),
+        mode => editor.mode = mode,
+    }
+}
+
+fn minibuffer_complete(editor: &mut Editor) {
+    if let EditorMode::SwitchBuffer(ref input) = editor.mode {
+        let mut input = input.clone();
+        editor.complete_buffer_input(&mut input);
+        editor.mode = EditorMode::SwitchBuffer(input);
+    }
+}
+
+fn minibuffer_delete_backward_char(editor: &mut Editor) {
+    if let Some(input) = editor.prompt_input() {
+        input.pop();
+    }
+}
+
+fn minibuffer_keyboard_quit(editor: &mut Editor) {
+    editor.mode = EditorMode::Normal;
+}
+
+fn answer_yes(editor: &mut Editor) -> bool {
+    match editor.mode {
+        EditorMode::PromptQuit => return true,
+        EditorMode::ConfirmRecover(idx) => editor.recover_file(idx, true),
+        EditorMode::ConfirmSave => editor.save_file_anyway(),
+        EditorMode::ConfirmRevert => editor.revert_buffer(),
+        EditorMode::ConfirmKill(idx) => editor.kill_buffer(idx),
+        EditorMode::ConfirmOverwrite(ref filename) => editor.write_file(filename.clone()),
+        _ => {}
+    }
+    false
+}
+
+fn answer_no(editor: &mut Editor) {
+    match editor.mode {
+        EditorMode::ConfirmRecover(idx) => editor.recover_file(idx, false),
+        EditorMode::PromptQuit
+        | EditorMode::ConfirmSave
+        | EditorMode::ConfirmRevert
+        | EditorMode::ConfirmKill(_)
+        | EditorMode::ConfirmOverwrite(_) => {
+            editor.mode = EditorMode::Normal;
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,162 @@
+/// Lines of context shown around each change in a unified diff.
+const CONTEXT: usize = 3;
+
+/// Past this many differences `diff` stops looking for the shortest edit
+/// script and replaces the rest wholesale, bounding time and memory.
+const MAX_EDIT_DISTANCE: usize = 4000;
+
+/// One step of turning the old lines into the new ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// The shortest edit script turning `old` into `new`, using Myers'
+/// algorithm on what is left after trimming the common ends.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut edits = vec![Edit::Keep; prefix];
+    edits.extend(myers(&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]));
+    edits.extend(std::iter::repeat_n(Edit::Keep, suffix));
+    edits
+}
+
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    // No more steps than this are taken, so v only needs room for them
+    let max = ((n + m) as usize).min(MAX_EDIT_DISTANCE);
+    let offset = max as isize + 1;
+    // v[k] is the furthest x reached on diagonal k = x - y
+    let mut v = vec![0isize; 2 * max + 3];
+    // The part of v that step d reads, saved for the walk back
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    let found = 'search: {
+        for d in 0..=max as isize {
+            trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+            for k in (-d..=d).step_by(2) {
+                let mut x = if k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize]) {
+                    v[(offset + k + 1) as usize]
+                } else {
+                    v[(offset + k - 1) as usize] + 1
+                };
+                let mut y = x - k;
+                while x < n && y < m && a[x as usize] == b[y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                v[(offset + k) as usize] = x;
+                if x >= n && y >= m {
+                    break 'search true;
+                }
+            }
+        }
+        false
+    };
+    if !found {
+        return replace_all(a.len(), b.len());
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, saved) in trace.iter().enumerate().skip(1).rev() {
+        let d = d as isize;
+        let at = |k: isize| saved[(k + d) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        edits.push(if x == prev_x { Edit::Insert } else { Edit::Delete });
+        (x, y) = (prev_x, prev_y);
+    }
+    edits.extend(std::iter::repeat_n(Edit::Keep, x as usize));
+    edits.reverse();
+    edits
+}
+
+fn replace_all(old_len: usize, new_len: usize) -> Vec<Edit> {
+    let mut edits = vec![Edit::Delete; old_len];
+    edits.extend(std::iter::repeat_n(Edit::Insert, new_len));
+    edits
+}
+
+/// The change from `old` to `new` in `diff -u` format, or an empty string
+/// if they are the same.
+pub fn unified(old_name: &str, new_name: &str, old: &[&str], new: &[&str]) -> String {
+    let edits = diff(old, new);
+    // Where each edit starts in the old and new lines
+    let mut starts = Vec::with_capacity(edits.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for edit in &edits {
+        starts.push((i, j));
+        match edit {
+            Edit::Keep => (i, j) = (i + 1, j + 1),
+            Edit::Delete => i += 1,
+            Edit::Insert => j += 1,
+        }
+    }
+    starts.push((i, j));
+
+    let changes: Vec<usize> = (0..edits.len()).filter(|&e| edits[e] != Edit::Keep).collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    let mut c = 0;
+    while c < changes.len() {
+        // Take every change whose context overlaps the previous one's
+        let first = changes[c];
+        let mut last = first;
+        while c + 1 < changes.len() && changes[c + 1] <= last + 2 * CONTEXT + 1 {
+            c += 1;
+            last = changes[c];
+        }
+        c += 1;
+
+        let start = first.saturating_sub(CONTEXT);
+        let end = (last + 1 + CONTEXT).min(edits.len());
+        let (old_start, new_start) = starts[start];
+        let (old_end, new_end) = starts[end];
+        let range = |start: usize, len: usize| if len == 0 { format!("{start},0") } else { format!("{},{len}", start + 1) };
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(old_start, old_end - old_start),
+            range(new_start, new_end - new_start),
+        ));
+        for (e, edit) in edits.iter().enumerate().take(end).skip(start) {
+            let (i, j) = starts[e];
+            match edit {
+                Edit::Keep => out.push_str(&format!(" {}\n", old[i])),
+                Edit::Delete => out.push_str(&format!("-{}\n", old[i])),
+                Edit::Insert => out.push_str(&format!("+{}\n", new[j])),
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_shortest_edit_script() {
+        let edits = diff(&["a", "b", "c", "d"], &["a", "x", "c", "d", "e"]);
+        assert_eq!(edits, [Edit::Keep, Edit::Delete, Edit::Insert, Edit::Keep, Edit::Keep, Edit::Insert]);
+    }
+}
@@ -0,0 +1,49 @@
+use std::borrow::Cow;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::editor::TAB_WIDTH;
+
+/// How a grapheme cluster starting at screen column `col` is written to the
+/// terminal. A tab becomes spaces up to the next multiple of `tab_width`.
+/// Other control characters are shown the way Emacs shows them: `^X` for
+/// ASCII ones and an octal escape for the rest, so they cannot move the
+/// terminal's cursor.
+pub fn grapheme_text(g: &str, col: usize, tab_width: usize) -> Cow<'_, str> {
+    match g.chars().next() {
+        Some('\t') => Cow::Owned(" ".repeat(tab_stop(col, tab_width) - col)),
+        Some(c @ ('\0'..='\x1f' | '\x7f')) => Cow::Owned(format!("^{}", char::from(c as u8 ^ 0x40))),
+        Some(c) if c.is_control() => Cow::Owned(format!("\\{:o}", c as u32)),
+        _ => Cow::Borrowed(g),
+    }
+}
+
+/// Columns a grapheme cluster starting at screen column `col` takes.
+pub fn grapheme_width(g: &str, col: usize, tab_width: usize) -> usize {
+    grapheme_text(g, col, tab_width).width()
+}
+
+/// The first tab stop after `col`.
+pub fn tab_stop(col: usize, tab_width: usize) -> usize {
+    let tab_width = tab_width.max(1);
+    (col / tab_width + 1) * tab_width
+}
+
+/// Columns `s` takes on screen when it starts at the left edge.
+pub fn str_width(s: &str) -> usize {
+    s.graphemes(true).fold(0, |used, g| used + grapheme_width(g, used, TAB_WIDTH))
+}
+
+/// The longest prefix of `s` that fits in `width` columns, without
+/// splitting a grapheme cluster.
+pub fn truncate(s: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (i, g) in s.grapheme_indices(true) {
+        used += grapheme_width(g, used, TAB_WIDTH);
+        if used > width {
+            return &s[..i];
+        }
+    }
+    s
+}
@@ -1,224 +1,461 @@
-use crossterm::{
-    cursor,
-    terminal::{self, ClearType},
-    queue,
-    execute,
-};
-
-use std::io::{self, stdout, Write};
+use crossterm::event;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::ops::Range;
+use std::path::Path;
 use std::process::Command as ShellCommand;
+use std::time::{Duration, Instant};
+
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::input::get_inputs;
 use crate::statusline::create_statusline;
-use crate::buffer::save_buffer;
+use crate::backend::{Backend, CrosstermBackend};
+use crate::buffer::{self, Backup, Buffer};
+use crate::diff;
+use crate::display::{grapheme_text, grapheme_width, str_width, tab_stop};
+use crate::encoding::{Encoding, FileFormat, LineEnding};
+use crate::keymap::{Key, Keymaps};
+use crate::killring::KillRing;
+use crate::screen::Screen;
+use crate::search::{ISearch, Replace};
+use crate::text::Text;
+use crate::window::{Layout, Window, WINDOW_MIN_HEIGHT, WINDOW_MIN_WIDTH};
 
 pub const VERSION: &str = "0.3.0";
+/// Columns between tab stops in new buffers.
 pub const TAB_WIDTH: usize = 4;
+pub const MAX_TAB_WIDTH: usize = 1000;
+pub const SCRATCH: &str = "*scratch*";
+pub const BUFFER_LIST: &str = "*Buffer List*";
+pub const STDIN: &str = "*stdin*";
+pub const DIFF: &str = "*Diff*";
+/// Keystrokes between auto-saves.
+pub const AUTO_SAVE_INTERVAL: usize = 300;
+/// Seconds of idleness after which buffers are auto-saved.
+pub const AUTO_SAVE_TIMEOUT: u64 = 30;
+/// Seconds between checks for files changed by other programs.
+pub const AUTO_REVERT_INTERVAL: u64 = 2;
+/// Smallest terminal the editor draws in. Below this it only says the
+/// terminal is too small.
+pub const MIN_TERM_WIDTH: usize = 10;
+pub const MIN_TERM_HEIGHT: usize = 3;
 
-#[derive(Debug, PartialEq)]
-pub enum Command {
-    None,
-    CtrlX,
+/// What the previous command did, for commands that behave differently
+/// when repeated.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Action {
+    Other,
+    Kill,
+    Yank,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum EditorMode {
     Normal,
-    SaveFile,
+    /// Reading the file name to write the buffer to.
+    SaveFile(String),
     PromptQuit,
     ShellCommand(String),
+    ISearch(ISearch),
+    ReplaceFrom { regexp: bool, from: String },
+    ReplaceTo { regexp: bool, from: String, to: String },
+    QueryReplace(Box<Replace>),
+    SwitchBuffer(String),
+    FindFile(String),
+    KillBuffer(String),
+    ConfirmKill(usize),
+    SetEncoding(String),
+    SetTabWidth(String),
+    GotoLine(String),
+    /// Asking whether to recover a buffer from its auto-save file, with the
+    /// differences shown in the current buffer.
+    ConfirmRecover(usize),
+    ConfirmSave,
+    /// Asking whether to write over the existing file named.
+    ConfirmOverwrite(String),
+    ConfirmRevert,
 }
 
 pub struct Editor {
     pub mode: EditorMode,
-    pub cmd: Command,
-    pub filename: String,
+    /// The keys typed so far of an unfinished key sequence.
+    pub keys: Vec<Key>,
+    pub keymaps: Keymaps,
+    pub buffers: Vec<Buffer>,
+    pub cur_buf: usize,
+    /// The buffer shown before the current one, the default for C-x b.
+    pub last_buf: usize,
     pub cur_x: usize,
     pub cur_y: usize,
-    pub buf: Vec<String>,
-    pub modified: bool,
     pub row_offset: usize,
     pub col_offset: usize,
-    pub last_frame: Vec<String>,
-    pub filename_given: bool,
+    /// What was last drawn, or is being drawn.
+    pub screen: Screen,
+    pub backend: Box<dyn Backend>,
     pub message: Option<String>,
-    pub original_buf: Vec<String>,
+    pub kill_ring: KillRing,
+    pub action: Action,
+    pub last_action: Action,
+    pub yank_start: usize,
+    pub transient_mark: bool,
+    pub search_history: Vec<String>,
+    pub windows: Vec<Window>,
+    pub cur_win: usize,
+    pub layout: Layout,
+    /// The backup kept of a file when a buffer is first saved.
+    pub backup: Backup,
+    pub keys_since_auto_save: usize,
 }
 
 impl Editor {
     pub fn new() -> Self {
-        Self {
-            mode: EditorMode::Normal,
-            cmd: Command::None,
-            filename: String::new(),
-            cur_x: 0,
-            cur_y: 0,
-            buf: vec![String::new()],
-            modified: false,
-            row_offset: 0,
-            col_offset: 0,
-            last_frame: vec![String::new()],
-            filename_given: false,
-            message: None,
-            original_buf: vec![String::new()],
-        }
+        Self::with_buffer(Buffer::new(SCRATCH.to_string()))
     }
 
-    pub fn from_file(filename: String, buf: Vec<String>) -> Self {
-        let original_buf = buf.clone();
-        Self {
-            mode: EditorMode::Normal,
-            cmd: Command::None,
-            filename,
-            cur_x: 0,
-            cur_y: 0,
-            buf,
-            modified: false,
-            row_offset: 0,
-            col_offset: 0,
-            last_frame: vec![String::new()],
-            filename_given: true,
-            message: None,
-            original_buf,
-        }
+    pub fn from_file(filename: String, buf: Text, format: FileFormat) -> Self {
+        Self::with_buffer(Buffer::from_file(filename, buf, format))
     }
 
     pub fn new_with_filename(filename: String) -> Self {
+        Self::with_buffer(Buffer::new_with_filename(filename))
+    }
+
+    /// Starts with a buffer holding text piped to standard input.
+    pub fn from_stdin(text: Text, format: FileFormat) -> Self {
+        Self::with_buffer(Buffer::from_text(STDIN.to_string(), text, format))
+    }
+
+    fn with_buffer(buffer: Buffer) -> Self {
         Self {
             mode: EditorMode::Normal,
-            cmd: Command::None,
-            filename,
+            keys: Vec::new(),
+            keymaps: Keymaps::default(),
+            buffers: vec![buffer],
+            cur_buf: 0,
+            last_buf: 0,
             cur_x: 0,
             cur_y: 0,
-            buf: vec![String::new()],
-            modified: false,
             row_offset: 0,
             col_offset: 0,
-            last_frame: vec![String::new()],
-            filename_given: true,
+            screen: Screen::new(0, 0),
+            backend: Box::new(CrosstermBackend::new()),
             message: None,
-            original_buf: vec![String::new()],
+            kill_ring: KillRing::new(),
+            action: Action::Other,
+            last_action: Action::Other,
+            yank_start: 0,
+            transient_mark: true,
+            search_history: Vec::new(),
+            windows: vec![Window::new(0)],
+            cur_win: 0,
+            layout: Layout::Window(0),
+            backup: Backup::default(),
+            keys_since_auto_save: 0,
         }
     }
 
-    pub fn start(&mut self) -> io::Result<()> {
-        let mut stdout = stdout();
-
-        terminal::enable_raw_mode()?;
-        queue!(
-            stdout, 
-            terminal::EnterAlternateScreen, 
-            cursor::EnableBlinking,
-            cursor::Show,
-        )?;
-
-        loop {
-            self.render(&mut stdout)?;
-
-            match get_inputs(self) {
-                Ok(true) => break,
-                Ok(false) => continue,
-                Err(e) => {
-                    eprintln!("Error: {e}");
-                    break;
-                }
-            }
-        }
-
-        queue!(stdout, terminal::LeaveAlternateScreen, cursor::Show)?;
-        terminal::disable_raw_mode()
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffers[self.cur_buf]
     }
 
-    fn render(&mut self, stdout: &mut io::Stdout) -> io::Result<()> {
-        let (_term_width, term_height) = terminal::size()?;
-        let term_height = term_height as usize;
-        let max_lines = (term_height - 2) as usize;
-
-        queue!(stdout, cursor::MoveTo(0, 0))?;
-        
-        let screen_lines = (term_height - 2) as usize;
-
-        if self.cur_y < self.row_offset {
-            self.row_offset = self.cur_y;
-        } else if self.cur_y >= self.row_offset + screen_lines {
-            self.row_offset = self.cur_y - screen_lines + 1;
-        }
+    pub fn buffer_mut(&mut self) -> &mut Buffer {
+        &mut self.buffers[self.cur_buf]
+    }
 
-        self.last_frame.truncate(max_lines);
+    /// The text of the current buffer.
+    pub fn text(&self) -> &Text {
+        &self.buffer().text
+    }
 
-        for i in 0..max_lines {
-            let buff_line = self.row_offset + i;
-            let screen_y = i as u16;
+    /// Runs the editor until it is told to exit. Nothing is printed; an
+    /// error ends the loop and is returned once the backend is left.
+    pub fn start(&mut self) -> io::Result<()> {
+        self.backend.enter()?;
+        let result = self.run();
+        self.backend.leave()?;
+        result
+    }
 
-            let new_line = if buff_line >= self.buf.len() {
-                "~".to_string()
-            } else {
-                self.buf[buff_line].clone()
-            };
+    fn run(&mut self) -> io::Result<()> {
+        let mut last_key = Instant::now();
+        loop {
+            self.render()?;
 
-            if self.last_frame.get(i) != Some(&new_line) {
-                queue!(
-                    stdout,
-                    cursor::MoveTo(0, screen_y),
-                    terminal::Clear(ClearType::CurrentLine),
-                )?;
-                write!(stdout, "{new_line}")?;
-
-                if i < self.last_frame.len() {
-                    self.last_frame[i] = new_line.clone();
-                } else {
-                    self.last_frame.push(new_line.clone());
+            if !event::poll(Duration::from_secs(AUTO_REVERT_INTERVAL))? {
+                self.auto_revert();
+                if last_key.elapsed() >= Duration::from_secs(AUTO_SAVE_TIMEOUT) {
+                    self.auto_save();
                 }
+                continue;
+            }
+            last_key = Instant::now();
+            self.keys_since_auto_save += 1;
+            if self.keys_since_auto_save >= AUTO_SAVE_INTERVAL {
+                self.auto_save();
             }
-        }
 
-        if self.cur_y >= self.row_offset + screen_lines {
-            self.row_offset = self.cur_y - screen_lines + 1;
+            if get_inputs(self)? {
+                return Ok(());
+            }
         }
+    }
 
-        let _ = create_statusline(self);
+    /// Draws the windows and echo area on the screen and shows it.
+    pub fn render(&mut self) -> io::Result<()> {
+        let (term_width, term_height) = self.backend.size()?;
+        let mut screen = std::mem::take(&mut self.screen);
+        screen.reset(term_width, term_height);
+        if term_width < MIN_TERM_WIDTH || term_height < MIN_TERM_HEIGHT {
+            screen.put_str(0, 0, "Terminal too small", false);
+            self.screen = screen;
+            return self.backend.draw(&self.screen);
+        }
+        let max_lines = term_height - 1;
 
-        queue!(stdout, cursor::MoveTo(0, (term_height - 1) as u16))?;
+        self.layout_windows(term_width, max_lines);
+        self.draw_windows(&mut screen);
 
         let cur_x;
         let cur_y;
 
-        let prompt_y = (term_height - 1) as u16;
+        let prompt_y = term_height - 1;
         match self.mode {
-            EditorMode::SaveFile => {
-                let tmp = "Write file: ".to_string();
-                write!(stdout, "{}{}", tmp, self.filename)?;
-                cur_x = (tmp.len() + self.filename.len()) as u16;
+            EditorMode::SaveFile(ref input) => {
+                let tmp = format!("Write file: {input}");
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
+                cur_y = prompt_y;
+            }
+            EditorMode::ConfirmOverwrite(ref filename) => {
+                let tmp = format!("File {filename} exists; overwrite? (y/n) ");
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
                 cur_y = prompt_y;
             }
             EditorMode::PromptQuit => {
-                let tmp = "Modified buffers exist. Leave anyway (y/n)? ".to_string();
-                write!(stdout, "{tmp}")?;
-                cur_x = tmp.len() as u16;
+                let tmp = format!(
+                    "Modified buffers exist ({}). Leave anyway (y/n)? ",
+                    self.modified_buffers().join(", "),
+                );
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
+                cur_y = prompt_y;
+            }
+            EditorMode::SwitchBuffer(ref input) => {
+                let tmp = format!(
+                    "Switch to buffer (default {}): {}{}",
+                    self.buffers[self.last_buf].name,
+                    input,
+                    self.completions_hint(input),
+                );
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = "Switch to buffer (default ): ".len()
+                    + str_width(&self.buffers[self.last_buf].name)
+                    + str_width(input);
+                cur_y = prompt_y;
+            }
+            EditorMode::FindFile(ref input) => {
+                let tmp = format!("Find file: {input}");
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
+                cur_y = prompt_y;
+            }
+            EditorMode::KillBuffer(ref input) => {
+                let tmp = format!("Kill buffer (default {}): {}", self.buffer().name, input);
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
+                cur_y = prompt_y;
+            }
+            EditorMode::SetEncoding(ref input) => {
+                let tmp = format!("Encoding for saving (default {}): {}", self.buffer().format, input);
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
+                cur_y = prompt_y;
+            }
+            EditorMode::SetTabWidth(ref input) => {
+                let tmp = format!("Tab width (default {}): {}", self.buffer().tab_width, input);
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
+                cur_y = prompt_y;
+            }
+            EditorMode::GotoLine(ref input) => {
+                let tmp = format!("Goto line: {input}");
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
+                cur_y = prompt_y;
+            }
+            EditorMode::ConfirmRecover(idx) => {
+                let path = self.buffers[idx].auto_save_path().unwrap_or_default();
+                let tmp = format!("Recover auto-save file {}? (y/n) ", path.display());
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
+                cur_y = prompt_y;
+            }
+            EditorMode::ConfirmSave => {
+                let tmp = format!("{} changed on disk; really save? (y/n) ", self.buffer().name);
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
+                cur_y = prompt_y;
+            }
+            EditorMode::ConfirmRevert => {
+                let tmp = format!("Revert buffer from file {}? (y/n) ", self.buffer().filename);
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
+                cur_y = prompt_y;
+            }
+            EditorMode::ConfirmKill(idx) => {
+                let tmp = format!("Buffer {} modified; kill anyway? (y/n) ", self.buffers[idx].name);
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
                 cur_y = prompt_y;
             }
             EditorMode::ShellCommand(ref cmd_str) => {
-                write!(stdout, "!{}", cmd_str)?;
-                cur_x = (1 + cmd_str.len()) as u16;
+                screen.put_str(0, prompt_y, &format!("!{cmd_str}"), false);
+                cur_x = 1 + str_width(cmd_str);
+                cur_y = prompt_y;
+            }
+            EditorMode::ISearch(ref search) => {
+                screen.put_str(0, prompt_y, &search.prompt(), false);
+                (cur_x, cur_y) = self.screen_cursor();
+            }
+            EditorMode::ReplaceFrom { regexp, ref from } => {
+                let tmp = format!("Query replace{}: {}", if regexp { " regexp" } else { "" }, from);
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
+                cur_y = prompt_y;
+            }
+            EditorMode::ReplaceTo { regexp, ref from, ref to } => {
+                let tmp = format!("Query replace{} {} with: {}", if regexp { " regexp" } else { "" }, from, to);
+                screen.put_str(0, prompt_y, &tmp, false);
+                cur_x = str_width(&tmp);
                 cur_y = prompt_y;
             }
+            EditorMode::QueryReplace(ref replace) => {
+                screen.put_str(0, prompt_y, &replace.prompt(), false);
+                (cur_x, cur_y) = self.screen_cursor();
+            }
             _ => {
-                cur_x = self.cur_x.saturating_sub(self.col_offset) as u16;
-                cur_y = self.cur_y.saturating_sub(self.row_offset) as u16;
+                (cur_x, cur_y) = self.screen_cursor();
             }
         }
 
-        if self.mode == EditorMode::Normal {
-            if let Some(ref msg) = self.message {
-                queue!(stdout, cursor::MoveTo(0, prompt_y), terminal::Clear(ClearType::CurrentLine))?;
-                queue!(stdout, crossterm::style::Print(msg))?;
-            }
+        if self.mode == EditorMode::Normal
+            && let Some(ref msg) = self.message
+        {
+            screen.put_str(0, prompt_y, msg, false);
         }
 
-        queue!(stdout, cursor::MoveTo(cur_x, cur_y))?;
-        stdout.flush()?;
+        screen.cursor = (cur_x, cur_y);
+        self.screen = screen;
         self.message = None;
-        Ok(())
+        self.backend.draw(&self.screen)
+    }
+
+    /// Stores the live cursor state in the selected window.
+    pub fn sync_window(&mut self) {
+        let win = &mut self.windows[self.cur_win];
+        win.buffer = self.cur_buf;
+        win.cur_x = self.cur_x;
+        win.cur_y = self.cur_y;
+        win.row_offset = self.row_offset;
+        win.col_offset = self.col_offset;
+    }
+
+    /// Lays out the windows in a `width` x `height` area and scrolls each
+    /// one so its cursor is visible.
+    fn layout_windows(&mut self, width: usize, height: usize) {
+        self.sync_window();
+        self.layout.arrange(&mut self.windows, 0, 0, width, height);
+        for win in &mut self.windows {
+            // Another window may have shortened the buffer
+            let buffer = &self.buffers[win.buffer];
+            let text = &buffer.text;
+            win.cur_y = win.cur_y.min(text.len_lines() - 1);
+            win.cur_x = win.cur_x.min(text.line_len(win.cur_y));
+            if buffer.visual_line {
+                let width = wrap_width(win.width);
+                let (row, _) = wrapped_pos(text, win.cur_y, win.cur_x, width, buffer.tab_width);
+                win.scroll_to_wrapped_cursor(row, |line| text.wrap_line(line, width, buffer.tab_width).len());
+            } else {
+                win.scroll_to_cursor(text.display_col(win.cur_y, win.cur_x, buffer.tab_width));
+            }
+        }
+        let win = &self.windows[self.cur_win];
+        self.row_offset = win.row_offset;
+        self.col_offset = win.col_offset;
+        self.cur_x = win.cur_x;
+        self.cur_y = win.cur_y;
+    }
+
+    /// Draws every window and its mode line.
+    fn draw_windows(&self, screen: &mut Screen) {
+        let highlight = if let EditorMode::ISearch(ref search) = self.mode {
+            search.found
+        } else if let EditorMode::QueryReplace(ref replace) = self.mode {
+            replace.found
+        } else if self.transient_mark && self.buffer().mark_active {
+            self.region()
+        } else {
+            None
+        };
+
+        for (idx, win) in self.windows.iter().enumerate() {
+            let buffer = &self.buffers[win.buffer];
+            let text = &buffer.text;
+            let highlight = if idx == self.cur_win { highlight } else { None };
+            if buffer.visual_line {
+                draw_wrapped(screen, buffer, win, highlight);
+            } else {
+                for i in 0..win.height {
+                    let buff_line = win.row_offset + i;
+                    if buff_line >= text.len_lines() {
+                        screen.put_str(win.left, win.top + i, "~", false);
+                    } else {
+                        let row = (win.left, win.top + i, win.width);
+                        draw_line(screen, row, buffer, buff_line, win.col_offset, highlight);
+                    }
+                }
+            }
+            if win.divider {
+                for i in 0..win.height {
+                    screen.put_str(win.left + win.width, win.top + i, "|", false);
+                }
+            }
+
+            let width = win.width + win.divider as usize;
+            let statusline = create_statusline(self, win, width);
+            screen.put_str(win.left, win.top + win.height, &statusline, true);
+        }
+    }
+
+    /// Where the cursor of the selected window is on screen.
+    fn screen_cursor(&self) -> (usize, usize) {
+        let win = &self.windows[self.cur_win];
+        let tab_width = self.buffer().tab_width;
+        if self.buffer().visual_line {
+            let width = wrap_width(win.width);
+            let (row, x) = wrapped_pos(self.text(), self.cur_y, self.cur_x, width, tab_width);
+            let above: usize = (self.row_offset..self.cur_y).map(|line| self.text().wrap_line(line, width, tab_width).len()).sum();
+            let y = (above + row).saturating_sub(win.skip_rows).min(win.height.saturating_sub(1));
+            return (win.left + x.min(win.width.saturating_sub(1)), win.top + y);
+        }
+        let col = self.text().display_col(self.cur_y, self.cur_x, tab_width);
+        let x = win.left + col.saturating_sub(self.col_offset).min(win.width.saturating_sub(1));
+        let y = win.top + self.cur_y.saturating_sub(self.row_offset);
+        (x, y)
+    }
+
+    /// Char index of the cursor in the buffer.
+    pub fn point(&self) -> usize {
+        self.text().pos_to_char(self.cur_y, self.cur_x)
+    }
+
+    pub fn set_point(&mut self, idx: usize) {
+        let (y, x) = self.text().char_to_pos(idx);
+        self.cur_y = y;
+        self.cur_x = x;
     }
 
     pub fn move_to_line_start(&mut self) {
@@ -226,214 +463,1361 @@ impl Editor {
     }
 
     pub fn move_to_line_end(&mut self) {
-        if let Some(line) = self.buf.get(self.cur_y) {
-            self.cur_x = line.len();
-        }
+        self.cur_x = self.text().line_len(self.cur_y);
+    }
+
+    /// Moves to line `y`, keeping the cursor in the same screen column as
+    /// far as the line allows.
+    fn move_to_line(&mut self, y: usize) {
+        let tab_width = self.buffer().tab_width;
+        let x = self.text().display_col(self.cur_y, self.cur_x, tab_width);
+        self.cur_y = y;
+        self.cur_x = self.text().col_at_display(y, x, tab_width);
     }
 
     pub fn move_next_line(&mut self) {
-        if self.cur_y + 1 < self.buf.len() {
-            self.cur_y += 1;
-            let len = self.buf[self.cur_y].len();
-            self.cur_x = self.cur_x.min(len);
+        if self.buffer().visual_line {
+            self.move_screen_rows(1);
+        } else if self.cur_y + 1 < self.text().len_lines() {
+            self.move_to_line(self.cur_y + 1);
         }
     }
 
     pub fn move_prev_line(&mut self) {
-        if self.cur_y > 0 {
+        if self.buffer().visual_line {
+            self.move_screen_rows(-1);
+        } else if self.cur_y > 0 {
+            self.move_to_line(self.cur_y - 1);
+        }
+    }
+
+    /// Moves `n` screen rows down, or up if `n` is negative, keeping the
+    /// cursor in the same screen column as far as the row allows. Used in
+    /// visual-line mode, where a line may take several rows.
+    fn move_screen_rows(&mut self, n: isize) {
+        let width = wrap_width(self.windows[self.cur_win].width);
+        let tab_width = self.buffer().tab_width;
+        let (mut row, x) = wrapped_pos(self.text(), self.cur_y, self.cur_x, width, tab_width);
+        let mut y = self.cur_y;
+        let mut rows = self.text().wrap_line(y, width, tab_width);
+        for _ in 0..n.unsigned_abs() {
+            if n > 0 && row + 1 < rows.len() {
+                row += 1;
+            } else if n > 0 && y + 1 < self.text().len_lines() {
+                y += 1;
+                rows = self.text().wrap_line(y, width, tab_width);
+                row = 0;
+            } else if n < 0 && row > 0 {
+                row -= 1;
+            } else if n < 0 && y > 0 {
+                y -= 1;
+                rows = self.text().wrap_line(y, width, tab_width);
+                row = rows.len() - 1;
+            } else {
+                break;
+            }
+        }
+
+        self.cur_y = y;
+        self.cur_x = self.text().col_at_display(y, rows[row].1 + x, tab_width);
+        // Stay on this row rather than landing at the start of the next
+        if let Some(&(next, _)) = rows.get(row + 1)
+            && self.cur_x >= next
+        {
+            self.cur_x = self.text().prev_grapheme(y, next);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        if self.cur_x > 0 {
+            self.cur_x = self.text().prev_grapheme(self.cur_y, self.cur_x);
+        } else if self.cur_y > 0 {
             self.cur_y -= 1;
-            let len = self.buf[self.cur_y].len();
-            self.cur_x = self.cur_x.min(len);
+            self.cur_x = self.text().line_len(self.cur_y);
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if self.cur_x < self.text().line_len(self.cur_y) {
+            self.cur_x = self.text().next_grapheme(self.cur_y, self.cur_x);
+        } else if self.cur_y + 1 < self.text().len_lines() {
+            self.cur_y += 1;
+            self.cur_x = 0;
+        }
+    }
+
+    /// Lines scrolled by C-v and C-z: the height of the selected window.
+    fn lines_per_page(&self) -> usize {
+        match self.windows[self.cur_win].height {
+            0 => self.backend.size().map_or(0, |(_, h)| h.saturating_sub(2)),
+            height => height,
         }
     }
 
     pub fn move_next_page(&mut self) {
-        let (_, term_height) = terminal::size().unwrap_or((0, 0));
-        let lines_per_page = term_height.saturating_sub(2) as usize;
+        let lines_per_page = self.lines_per_page();
 
-        if self.cur_y + 1 < self.buf.len() {
-            self.cur_y = (self.cur_y + lines_per_page).min(self.buf.len() - 1);
-            let len = self.buf[self.cur_y].len();
-            self.cur_x = self.cur_x.min(len);
+        if self.buffer().visual_line {
+            self.move_screen_rows(lines_per_page as isize);
+        } else if self.cur_y + 1 < self.text().len_lines() {
+            self.move_to_line((self.cur_y + lines_per_page).min(self.text().len_lines() - 1));
         }
     }
 
     pub fn move_prev_page(&mut self) {
-        let (_, term_height) = terminal::size().unwrap_or((0, 0));              
-        let lines_per_page = term_height.saturating_sub(2) as usize;            
-                                                                                
-        if self.cur_y > 0 {                                    
-            self.cur_y = self.cur_y.saturating_sub(lines_per_page);
-            let len = self.buf[self.cur_y].len();                               
-            self.cur_x = self.cur_x.min(len);                                   
-        }   
+        let lines_per_page = self.lines_per_page();
+
+        if self.buffer().visual_line {
+            self.move_screen_rows(-(lines_per_page as isize));
+        } else if self.cur_y > 0 {
+            self.move_to_line(self.cur_y.saturating_sub(lines_per_page));
+        }
+    }
+
+    /// Removes the chars between `start` and `end` and leaves point at
+    /// `start`.
+    pub fn delete_range(&mut self, start: usize, end: usize) -> String {
+        if !self.check_writable() {
+            return String::new();
+        }
+        let point = self.point();
+        let others = self.other_window_points();
+        let removed = self.buffer_mut().delete(start, end, point);
+        self.set_point(start);
+        self.move_other_windows(others, |p| {
+            if p >= end {
+                p - (end - start)
+            } else {
+                p.min(start)
+            }
+        });
+        removed
+    }
+
+    /// Inserts `s` at point and leaves point after it.
+    pub fn insert_str(&mut self, s: &str) {
+        self.insert_text(s, false);
+    }
+
+    fn insert_text(&mut self, s: &str, typing: bool) {
+        if !self.check_writable() {
+            return;
+        }
+        let at = self.point();
+        let len = s.chars().count();
+        let others = self.other_window_points();
+        self.buffer_mut().insert(at, s, at, typing);
+        self.set_point(at + len);
+        self.move_other_windows(others, |p| if p > at { p + len } else { p });
+    }
+
+    /// Reports an error and returns false if the current buffer is
+    /// read-only.
+    fn check_writable(&mut self) -> bool {
+        if self.buffer().read_only {
+            self.message = Some(format!("Buffer is read-only: {}", self.buffer().name));
+            return false;
+        }
+        true
+    }
+
+    pub fn toggle_read_only(&mut self) {
+        let buffer = self.buffer_mut();
+        buffer.read_only = !buffer.read_only;
+        self.message = Some(if buffer.read_only { "Read-only mode enabled" } else { "Read-only mode disabled" }.to_string());
+    }
+
+    /// Moves to 1-based `line` and `col`, clamped to the buffer.
+    pub fn goto_line(&mut self, line: usize, col: usize) {
+        self.cur_y = line.saturating_sub(1).min(self.text().len_lines() - 1);
+        self.cur_x = col.saturating_sub(1).min(self.text().line_len(self.cur_y));
+    }
+
+    pub fn prompt_goto_line(&mut self) {
+        self.mode = EditorMode::GotoLine(String::new());
+    }
+
+    /// Moves to the start of the line numbered `line`. An empty line
+    /// number stays put.
+    pub fn goto_line_number(&mut self, line: &str) {
+        self.mode = EditorMode::Normal;
+        if line.is_empty() {
+            return;
+        }
+        match line.trim().parse::<usize>() {
+            Ok(line) => self.goto_line(line, 1),
+            Err(_) => self.message = Some("Please enter a number".to_string()),
+        }
+    }
+
+    /// Points of the other windows showing the current buffer.
+    fn other_window_points(&self) -> Vec<(usize, usize)> {
+        self.windows
+            .iter()
+            .enumerate()
+            .filter(|(i, win)| *i != self.cur_win && win.buffer == self.cur_buf)
+            .map(|(i, win)| (i, self.text().pos_to_char(win.cur_y, win.cur_x)))
+            .collect()
+    }
+
+    /// Moves the other windows' points to follow an edit.
+    fn move_other_windows(&mut self, points: Vec<(usize, usize)>, f: impl Fn(usize) -> usize) {
+        for (i, point) in points {
+            let (y, x) = self.text().char_to_pos(f(point));
+            self.windows[i].cur_y = y;
+            self.windows[i].cur_x = x;
+        }
+    }
+
+    pub fn undo(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        let buffer = self.buffer_mut();
+        match buffer.undo.undo(&mut buffer.text) {
+            Some(point) => {
+                buffer.update_modified();
+                self.set_point(point);
+                self.message = Some("Undo!".to_string());
+            }
+            None => {
+                self.message = Some("No further undo information".to_string());
+            }
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        let buffer = self.buffer_mut();
+        match buffer.undo.redo(&mut buffer.text) {
+            Some(point) => {
+                buffer.update_modified();
+                self.set_point(point);
+                self.message = Some("Redo!".to_string());
+            }
+            None => {
+                self.message = Some("No further redo information".to_string());
+            }
+        }
+    }
+
+    /// Deletes the chars between `start` and `end` and saves them in the
+    /// kill ring. Consecutive kills add to the same entry. In a read-only
+    /// buffer the text is only copied, and point moves over it instead.
+    pub fn kill_range(&mut self, start: usize, end: usize) {
+        let backward = start < self.point();
+        let read_only = self.buffer().read_only;
+        let text = if read_only { self.text().slice(start..end) } else { self.delete_range(start, end) };
+        if read_only {
+            self.message = Some("Buffer is read-only, text copied to kill ring".to_string());
+            if !backward {
+                self.set_point(end);
+            }
+        }
+        if self.last_action == Action::Kill {
+            if backward {
+                self.kill_ring.prepend(&text);
+            } else {
+                self.kill_ring.append(&text);
+            }
+        } else {
+            self.kill_ring.push(text);
+        }
+        self.action = Action::Kill;
     }
 
     pub fn kill_to_eol(&mut self) {
-        if let Some(line) = self.buf.get_mut(self.cur_y) {
-            line.truncate(self.cur_x);
-            self.update_modified();
+        let start = self.point();
+        let end = self.text().line_to_char(self.cur_y) + self.text().line_len(self.cur_y);
+        if start < end {
+            self.kill_range(start, end);
+        } else if start < self.text().len_chars() {
+            // At the end of the line, kill the newline
+            self.kill_range(start, start + 1);
         }
     }
 
+    /// Deletes the grapheme cluster before point, or the newline at the
+    /// start of the line.
     pub fn del_prev_char(&mut self) {
+        let at = self.point();
         if self.cur_x > 0 {
-            self.buf[self.cur_y].remove(self.cur_x - 1);
-            self.cur_x -= 1;
-            self.update_modified();
-        } else if self.cur_y > 0 {
-            let prev_line_len = self.buf[self.cur_y - 1].len();
-            let current_line = self.buf.remove(self.cur_y);
-            self.cur_y -= 1;
-            self.cur_x = prev_line_len;
-            self.buf[self.cur_y].push_str(&current_line);
-            self.update_modified();
+            let start = at - self.cur_x + self.text().prev_grapheme(self.cur_y, self.cur_x);
+            self.delete_range(start, at);
+        } else if at > 0 {
+            self.delete_range(at - 1, at);
         }
     }
 
+    /// Deletes the grapheme cluster after point, or the newline at the end
+    /// of the line.
     pub fn del_next_char(&mut self) {
-        if self.cur_x < self.buf[self.cur_y].len() {
-            self.buf[self.cur_y].remove(self.cur_x);
-            self.update_modified();
-        } else if self.cur_y + 1 < self.buf.len() {
-            let next_line = self.buf.remove(self.cur_y + 1);
-            self.buf[self.cur_y].push_str(&next_line);
-            self.update_modified();
+        let at = self.point();
+        if self.cur_x < self.text().line_len(self.cur_y) {
+            let end = at - self.cur_x + self.text().next_grapheme(self.cur_y, self.cur_x);
+            self.delete_range(at, end);
+        } else if at < self.text().len_chars() {
+            self.delete_range(at, at + 1);
         }
     }
 
     pub fn del_next_word(&mut self) {
-        if let Some(line) = self.buf.get_mut(self.cur_y) {
-            let rest = &line[self.cur_x..];
-            if let Some(pos) = rest.find(char::is_whitespace) {
-                let mut end = self.cur_x + pos;
-                // Skip over following whitespace
-                while end < line.len() && line.as_bytes()[end].is_ascii_whitespace() {
-                    end += 1;
-                }
-                line.drain(self.cur_x..end);
-            } else {
-                line.truncate(self.cur_x);
-            }
-            self.update_modified();
+        let start = self.point();
+        let line_end = self.text().line_to_char(self.cur_y) + self.text().line_len(self.cur_y);
+        let mut end = start;
+        let mut chars = self.text().chars_at(start).peekable();
+        // Skip to the end of the word, then over following whitespace
+        while end < line_end && chars.next_if(|c| !c.is_whitespace()).is_some() {
+            end += 1;
+        }
+        while end < line_end && chars.next_if(|c| c.is_whitespace()).is_some() {
+            end += 1;
+        }
+        if start < end {
+            self.kill_range(start, end);
         }
     }
 
-    // TODO: FIX, NOT WORKING
     pub fn del_prev_word(&mut self) {
-        if let Some(line) = self.buf.get_mut(self.cur_y) {
-            if self.cur_x == 0 && self.cur_y > 0 {
-                // Merge with previous line
-                let current_line = self.buf.remove(self.cur_y);
-                self.cur_y -= 1;
-                self.cur_x = self.buf[self.cur_y].len();
-                self.buf[self.cur_y].push_str(&current_line);
-                self.update_modified();
-            } else if self.cur_x > 0 {
-                let before = &line[..self.cur_x];
-                let idx = before.trim_end_matches(char::is_whitespace).rfind(char::is_whitespace)
-                    .map(|i| i + 1)
-                    .unwrap_or(0);
-                line.drain(idx..self.cur_x);
-                self.cur_x = idx;
-                self.update_modified();
+        let end = self.point();
+        if self.cur_x == 0 {
+            // Join with the previous line
+            if end > 0 {
+                self.kill_range(end - 1, end);
             }
+            return;
+        }
+        let line_start = end - self.cur_x;
+        let mut start = end;
+        // Skip whitespace before point, then the word itself
+        while start > line_start && self.text().char(start - 1).is_some_and(char::is_whitespace) {
+            start -= 1;
+        }
+        while start > line_start && self.text().char(start - 1).is_some_and(|c| !c.is_whitespace()) {
+            start -= 1;
         }
+        self.kill_range(start, end);
     }
 
-    pub fn quick_exit(&mut self) -> bool {
-        match save_buffer(&self.buf, &self.filename) {
-            Ok(_) => {
-                return true;
+    pub fn yank(&mut self) {
+        let Some(text) = self.kill_ring.yank().map(str::to_string) else {
+            self.message = Some("Kill ring is empty".to_string());
+            return;
+        };
+        self.yank_start = self.point();
+        self.insert_str(&text);
+        self.buffer_mut().mark = Some(self.yank_start);
+        self.action = Action::Yank;
+    }
+
+    /// Replaces the text just yanked with the next older kill.
+    pub fn yank_pop(&mut self) {
+        if self.last_action != Action::Yank {
+            self.message = Some("Previous command was not a yank".to_string());
+            return;
+        }
+        let Some(text) = self.kill_ring.rotate().map(str::to_string) else {
+            return;
+        };
+        // Commands run at a prompt do not count as the last action, so point
+        // may have moved back past the yank since
+        let end = self.point();
+        let start = self.yank_start.min(end);
+        self.delete_range(start, end);
+        self.insert_str(&text);
+        self.buffer_mut().mark = Some(start);
+        self.action = Action::Yank;
+    }
+
+    pub fn set_mark(&mut self) {
+        let point = self.point();
+        let buffer = self.buffer_mut();
+        buffer.mark = Some(point);
+        buffer.mark_active = true;
+        self.message = Some("Mark set".to_string());
+    }
+
+    pub fn exchange_point_and_mark(&mut self) {
+        let Some(mark) = self.buffer().mark else {
+            self.message = Some("No mark set in this buffer".to_string());
+            return;
+        };
+        let mark = mark.min(self.text().len_chars());
+        let point = self.point();
+        let buffer = self.buffer_mut();
+        buffer.mark = Some(point);
+        buffer.mark_active = true;
+        self.set_point(mark);
+    }
+
+    /// The text between point and mark, as ordered char indices.
+    pub fn region(&self) -> Option<(usize, usize)> {
+        let mark = self.buffer().mark?.min(self.text().len_chars());
+        let point = self.point();
+        Some((mark.min(point), mark.max(point)))
+    }
+
+    fn region_or_message(&mut self) -> Option<(usize, usize)> {
+        let region = self.region();
+        if region.is_none() {
+            self.message = Some("The mark is not set now".to_string());
+        }
+        region
+    }
+
+    pub fn kill_region(&mut self) {
+        if let Some((start, end)) = self.region_or_message() {
+            self.kill_range(start, end);
+        }
+    }
+
+    pub fn copy_region(&mut self) {
+        let Some((start, end)) = self.region_or_message() else {
+            return;
+        };
+        let text = self.text().slice(start..end);
+        if self.last_action == Action::Kill {
+            self.kill_ring.append(&text);
+        } else {
+            self.kill_ring.push(text);
+        }
+        self.action = Action::Kill;
+        self.buffer_mut().mark_active = false;
+    }
+
+    /// Replaces the chars between `start` and `end` with `text`, keeping
+    /// point where it was.
+    fn replace_range(&mut self, start: usize, end: usize, text: &str) {
+        let point = self.point();
+        let mark = self.buffer().mark;
+        self.delete_range(start, end);
+        self.insert_str(text);
+        self.buffer_mut().mark = mark;
+        self.set_point(point);
+    }
+
+    pub fn upcase_region(&mut self) {
+        if let Some((start, end)) = self.region_or_message() {
+            let text = self.text().slice(start..end).to_uppercase();
+            self.replace_range(start, end, &text);
+        }
+    }
+
+    pub fn downcase_region(&mut self) {
+        if let Some((start, end)) = self.region_or_message() {
+            let text = self.text().slice(start..end).to_lowercase();
+            self.replace_range(start, end, &text);
+        }
+    }
+
+    /// Indents every non-empty line touched by the region by one tab, or by
+    /// the tab width in spaces if the buffer does not indent with tabs.
+    pub fn indent_region(&mut self) {
+        let Some((start, end)) = self.region_or_message() else {
+            return;
+        };
+        let first = self.text().char_to_line(start);
+        let mut last = self.text().char_to_line(end);
+        if last > first && self.text().line_to_char(last) == end {
+            last -= 1;
+        }
+
+        let mut point = self.point();
+        let indent = self.indent_unit();
+        let indent_len = indent.chars().count();
+        for line in first..=last {
+            if self.text().line_len(line) == 0 {
+                continue;
+            }
+            let at = self.text().line_to_char(line);
+            if point > at {
+                point += indent_len;
             }
+            self.set_point(at);
+            self.insert_str(&indent);
+        }
+        self.set_point(point);
+    }
+
+    pub fn isearch(&mut self, forward: bool) {
+        self.mode = EditorMode::ISearch(ISearch::new(forward, self.point()));
+    }
+
+    /// Ends an incremental search, leaving point at the match.
+    pub fn isearch_done(&mut self, search: ISearch) {
+        if !search.query.is_empty() {
+            self.search_history.retain(|q| q != &search.query);
+            self.search_history.push(search.query.clone());
+        }
+        if self.point() != search.origin {
+            self.buffer_mut().mark = Some(search.origin);
+            self.message = Some("Mark saved where search started".to_string());
+        }
+        self.mode = EditorMode::Normal;
+    }
+
+    pub fn query_replace(&mut self, regexp: bool) {
+        self.mode = EditorMode::ReplaceFrom { regexp, from: String::new() };
+    }
+
+    /// Starts replacing `from` with `to` from point onwards.
+    pub fn start_replace(&mut self, from: String, to: String, regexp: bool) {
+        match Replace::new(from, to, regexp, self.text(), self.point()) {
+            Ok(replace) => self.replace_next(Box::new(replace)),
             Err(e) => {
-                self.message = Some(format!("Error saving file, cannot exit: {e}"));
+                self.message = Some(format!("Invalid regexp: {e}"));
                 self.mode = EditorMode::Normal;
-                return false;
             }
         }
     }
 
-    pub fn insert_char(&mut self, c: char) {
-        if self.cur_y >= self.buf.len() {
-            self.buf.push(String::new());
+    /// Moves to the next match of a query-replace session, or ends it if
+    /// there are none left.
+    pub fn replace_next(&mut self, mut replace: Box<Replace>) {
+        match replace.next_match() {
+            Some((_, end)) => {
+                self.set_point(end);
+                self.mode = EditorMode::QueryReplace(replace);
+            }
+            None => self.replace_done(&replace),
         }
-        self.buf[self.cur_y].insert(self.cur_x, c);
-        self.cur_x += 1;
-        self.update_modified();
     }
 
-    pub fn save_file(&mut self) {
-        self.cmd = Command::None;
-        if self.filename_given && !self.filename.is_empty() {
-            match save_buffer(&self.buf, &self.filename) {
-                Ok(_) => {
-                    self.original_buf = self.buf.clone();
-                    self.modified = false;
-                    let count = self.buf.len();
-                    self.message = Some(format!("(Wrote {} lines)", count));
-                } 
+    pub fn replace_match(&mut self, replace: &mut Replace) {
+        let Some((start, end)) = replace.found else {
+            return;
+        };
+        let text = replace.replacement();
+        self.delete_range(start, end);
+        self.insert_str(&text);
+        replace.replaced_with(text.chars().count());
+    }
+
+    pub fn replace_done(&mut self, replace: &Replace) {
+        let n = replace.replaced;
+        self.message = Some(format!("Replaced {} occurrence{}", n, if n == 1 { "" } else { "s" }));
+        self.mode = EditorMode::Normal;
+    }
+
+    pub fn keyboard_quit(&mut self) {
+        self.buffer_mut().mark_active = false;
+        self.message = Some("Quit".to_string());
+    }
+
+    /// Saves every modified buffer and exits. A modified buffer without a
+    /// file, such as `*stdin*`, is shown and asked a file name for first.
+    pub fn quick_exit(&mut self) -> bool {
+        if let Some(buffer) = self.buffers.iter().find(|b| b.modified && b.changed_on_disk()) {
+            self.message = Some(format!("{} changed on disk, cannot exit; save it with C-x C-s", buffer.name));
+            return false;
+        }
+        if let Some(idx) = self.buffers.iter().position(|b| b.modified && b.filename.is_empty()) {
+            self.switch_to_buffer(idx);
+            self.write_buffer();
+            return false;
+        }
+        let mut report = Vec::new();
+        for buffer in self.buffers.iter_mut().filter(|b| b.modified) {
+            match buffer.save(self.backup) {
+                Ok(summary) => report.push(format!("Wrote {} ({summary})", buffer.filename)),
                 Err(e) => {
-                    eprintln!("Error saving file {e}");
+                    self.message = Some(format!("Error saving {}, cannot exit: {e}", buffer.name));
+                    self.mode = EditorMode::Normal;
+                    return false;
                 }
             }
+        }
+        // Shown once the terminal is restored
+        self.message = (!report.is_empty()).then(|| report.join("\n"));
+        true
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        self.insert_text(c.encode_utf8(&mut [0; 4]), true);
+    }
+
+    pub fn insert_newline(&mut self) {
+        self.insert_str("\n");
+    }
+
+    pub fn save_file(&mut self) {
+        let buffer = self.buffer();
+        if !buffer.filename_given || buffer.filename.is_empty() {
+            self.write_buffer();
+        } else if !buffer.modified {
+            self.message = Some("(No changes need to be saved)".to_string());
+        } else if buffer.changed_on_disk() {
+            self.mode = EditorMode::ConfirmSave;
         } else {
-            self.mode = EditorMode::SaveFile;
+            self.save_file_anyway();
+        }
+    }
+
+    /// Saves the current buffer to its file without checking whether the
+    /// file changed on disk.
+    pub fn save_file_anyway(&mut self) {
+        self.mode = EditorMode::Normal;
+        let backup = self.backup;
+        match self.buffer_mut().save(backup) {
+            Ok(summary) => {
+                self.message = Some(format!("Wrote {} ({summary})", self.buffer().filename));
+            } 
+            Err(e) => {
+                self.message = Some(format!("Error saving file: {e}"));
+            }
+        }
+    }
+
+    /// Reloads the current buffer from its file, asking first if it has
+    /// unsaved changes.
+    pub fn prompt_revert_buffer(&mut self) {
+        if self.buffer().filename.is_empty() {
+            self.message = Some("Buffer does not seem to be associated with any file".to_string());
+        } else if self.buffer().modified {
+            self.mode = EditorMode::ConfirmRevert;
+        } else {
+            self.revert_buffer();
+        }
+    }
+
+    pub fn revert_buffer(&mut self) {
+        self.mode = EditorMode::Normal;
+        match self.revert_buffer_at(self.cur_buf) {
+            Ok(()) => self.message = Some(format!("Reverted buffer {}", self.buffer().name)),
+            Err(e) => self.message = Some(format!("Cannot revert {}: {e}", self.buffer().filename)),
+        }
+    }
+
+    /// Reloads buffer `idx` from its file, keeping point where it was as far
+    /// as the new text allows.
+    fn revert_buffer_at(&mut self, idx: usize) -> io::Result<()> {
+        let point = if idx == self.cur_buf { self.point() } else { self.buffers[idx].point };
+        self.buffers[idx].revert()?;
+        let point = point.min(self.buffers[idx].text.len_chars());
+        if idx == self.cur_buf {
+            self.set_point(point);
+        } else {
+            self.buffers[idx].point = point;
+        }
+        Ok(())
+    }
+
+    /// Reverts every unmodified buffer whose file another program changed.
+    pub fn auto_revert(&mut self) {
+        if self.mode != EditorMode::Normal {
+            return;
+        }
+        for idx in 0..self.buffers.len() {
+            let buffer = &self.buffers[idx];
+            if buffer.modified || buffer.filename.is_empty() || !buffer.changed_on_disk() {
+                continue;
+            }
+            match self.revert_buffer_at(idx) {
+                Ok(()) => self.message = Some(format!("Reverted buffer {}", self.buffers[idx].name)),
+                Err(e) => self.message = Some(format!("Cannot revert {}: {e}", self.buffers[idx].filename)),
+            }
         }
     }
 
     pub fn quit(&mut self) -> bool {
-        if !self.modified {
+        if !self.buffers.iter().any(|b| b.modified) {
             return true;
-        } else {
-            self.mode = EditorMode::PromptQuit;
         }
+        self.mode = EditorMode::PromptQuit;
         false
     }
 
+    /// Names of the buffers with unsaved changes.
+    pub fn modified_buffers(&self) -> Vec<&str> {
+        self.buffers.iter().filter(|b| b.modified).map(|b| b.name.as_str()).collect()
+    }
+
     pub fn write_buffer(&mut self) {
-        self.mode = EditorMode::SaveFile;
-        self.cmd = Command::None;
+        self.mode = EditorMode::SaveFile(self.buffer().filename.clone());
     }
 
-    pub fn update_modified(&mut self) {
-        self.modified = self.buf != self.original_buf;
+    /// Saves the current buffer to `filename`, which it visits from then on.
+    pub fn write_file(&mut self, filename: String) {
+        let buffer = self.buffer_mut();
+        if buffer.filename != filename {
+            buffer.filename = filename;
+            // The file written to gets a backup of its own
+            buffer.backed_up = false;
+        }
+        self.save_file_anyway();
+        self.rename_to_filename();
+    }
+
+    /// Makes buffer `idx` current, keeping the old buffer's point.
+    pub fn switch_to_buffer(&mut self, idx: usize) {
+        if idx == self.cur_buf {
+            return;
+        }
+        let point = self.point();
+        self.buffer_mut().point = point;
+        self.last_buf = self.cur_buf;
+        self.cur_buf = idx;
+        let point = self.buffer().point;
+        self.set_point(point);
+        self.row_offset = 0;
+        self.col_offset = 0;
+        self.windows[self.cur_win].min_col_offset = 0;
+    }
+
+    pub fn find_buffer(&self, name: &str) -> Option<usize> {
+        self.buffers.iter().position(|b| b.name == name)
+    }
+
+    /// Adds `buffer`, renaming it if the name is taken, and makes it current.
+    pub fn add_buffer(&mut self, mut buffer: Buffer) {
+        let base = buffer.name.clone();
+        let mut n = 2;
+        while self.find_buffer(&buffer.name).is_some() {
+            buffer.name = format!("{base}<{n}>");
+            n += 1;
+        }
+        self.buffers.push(buffer);
+        self.switch_to_buffer(self.buffers.len() - 1);
+    }
+
+    /// Buffer names starting with `prefix`.
+    pub fn complete_buffer_name(&self, prefix: &str) -> Vec<&str> {
+        self.buffers
+            .iter()
+            .map(|b| b.name.as_str())
+            .filter(|name| name.starts_with(prefix))
+            .collect()
+    }
+
+    /// Extends `input` to the longest prefix shared by its completions.
+    pub fn complete_buffer_input(&self, input: &mut String) {
+        let matches = self.complete_buffer_name(input);
+        let Some(first) = matches.first() else {
+            return;
+        };
+        let mut common = first.to_string();
+        for name in &matches[1..] {
+            let len = common
+                .char_indices()
+                .zip(name.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(common.len().min(name.len()), |((i, _), _)| i);
+            common.truncate(len);
+        }
+        if common.len() > input.len() {
+            *input = common;
+        }
+    }
+
+    /// The completions for `input`, shown after it in the prompt.
+    fn completions_hint(&self, input: &str) -> String {
+        let matches = self.complete_buffer_name(input);
+        if input.is_empty() || matches.len() < 2 {
+            return String::new();
+        }
+        format!(" {{{}}}", matches.join(" | "))
+    }
+
+    pub fn prompt_switch_buffer(&mut self) {
+        if self.last_buf >= self.buffers.len() || self.last_buf == self.cur_buf {
+            self.last_buf = (self.cur_buf + 1) % self.buffers.len();
+        }
+        self.mode = EditorMode::SwitchBuffer(String::new());
+    }
+
+    pub fn prompt_switch_buffer_other_window(&mut self) {
+        self.pop_to_other_window();
+        self.prompt_switch_buffer();
+    }
+
+    /// Switches to the buffer called `name`, creating it if needed. An empty
+    /// name means the previous buffer.
+    pub fn switch_buffer_by_name(&mut self, name: &str) {
+        self.mode = EditorMode::Normal;
+        if name.is_empty() {
+            self.switch_to_buffer(self.last_buf);
+            return;
+        }
+        match self.find_buffer(name) {
+            Some(idx) => self.switch_to_buffer(idx),
+            None => self.add_buffer(Buffer::new(name.to_string())),
+        }
     }
 
+    /// Renames the current buffer after the file it visits.
+    pub fn rename_to_filename(&mut self) {
+        let name = buffer::buffer_name(&self.buffer().filename);
+        if self.find_buffer(&name).is_none() {
+            self.buffer_mut().name = name;
+        }
+    }
+
+    pub fn prompt_find_file(&mut self) {
+        let dir = Path::new(&self.buffer().filename)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::current_dir().ok())
+            .map(|dir| format!("{}/", dir.display()))
+            .unwrap_or_default();
+        self.mode = EditorMode::FindFile(dir);
+    }
+
+    pub fn prompt_find_file_other_window(&mut self) {
+        self.pop_to_other_window();
+        self.prompt_find_file();
+    }
+
+    /// Visits `filename` in its own buffer, reusing a buffer already visiting
+    /// it. Returns whether that buffer is now current, which it is not if
+    /// the file could not be read.
+    pub fn find_file(&mut self, filename: &str) -> bool {
+        self.mode = EditorMode::Normal;
+        if let Some(idx) = self.buffers.iter().position(|b| b.filename == filename) {
+            self.switch_to_buffer(idx);
+            return true;
+        }
+        if !Path::new(filename).exists() {
+            self.add_buffer(Buffer::new_with_filename(filename.to_string()));
+            self.message = Some("(New file)".to_string());
+            return true;
+        }
+        match buffer::load_buffer(filename) {
+            Ok((text, format)) => {
+                self.add_buffer(Buffer::from_file(filename.to_string(), text, format));
+                true
+            }
+            Err(e) => {
+                self.message = Some(format!("Cannot read {filename}: {e}"));
+                false
+            }
+        }
+    }
+
+    pub fn prompt_kill_buffer(&mut self) {
+        self.mode = EditorMode::KillBuffer(String::new());
+    }
+
+    /// Kills the buffer called `name`, asking first if it is modified. An
+    /// empty name means the current buffer.
+    pub fn kill_buffer_by_name(&mut self, name: &str) {
+        self.mode = EditorMode::Normal;
+        let idx = if name.is_empty() { Some(self.cur_buf) } else { self.find_buffer(name) };
+        match idx {
+            Some(idx) if self.buffers[idx].modified => self.mode = EditorMode::ConfirmKill(idx),
+            Some(idx) => self.kill_buffer(idx),
+            None => self.message = Some(format!("No buffer named {name}")),
+        }
+    }
+
+    pub fn prompt_set_encoding(&mut self) {
+        self.mode = EditorMode::SetEncoding(String::new());
+    }
+
+    /// Makes the current buffer save in the encoding and line ending named
+    /// by `name`, such as `latin-1`, `dos` or `utf-8-unix`. An empty name
+    /// keeps the current format.
+    pub fn set_encoding(&mut self, name: &str) {
+        self.mode = EditorMode::Normal;
+        if name.is_empty() {
+            return;
+        }
+        let Some(format) = self.buffer().format.with_name(name) else {
+            let names: Vec<_> = Encoding::ALL.iter().map(|e| e.name()).collect();
+            self.message = Some(format!(
+                "Unknown encoding {name}; try one of {}, optionally ending in -{}, -{} or -{}",
+                names.join(", "),
+                LineEnding::Lf.name(),
+                LineEnding::CrLf.name(),
+                LineEnding::Cr.name(),
+            ));
+            return;
+        };
+        let buffer = self.buffer_mut();
+        if buffer.format != format {
+            buffer.format = format;
+            // The file no longer matches what would be saved
+            buffer.modified = true;
+            buffer.undo.mark_unsaved();
+        }
+        self.message = Some(format!("Will save as {format}"));
+    }
+
+    pub fn prompt_set_tab_width(&mut self) {
+        self.mode = EditorMode::SetTabWidth(String::new());
+    }
+
+    /// The text being typed at the current prompt, if it reads one.
+    pub fn prompt_input(&mut self) -> Option<&mut String> {
+        match self.mode {
+            EditorMode::SaveFile(ref mut input)
+            | EditorMode::ShellCommand(ref mut input)
+            | EditorMode::SwitchBuffer(ref mut input)
+            | EditorMode::FindFile(ref mut input)
+            | EditorMode::KillBuffer(ref mut input)
+            | EditorMode::SetEncoding(ref mut input)
+            | EditorMode::SetTabWidth(ref mut input)
+            | EditorMode::GotoLine(ref mut input)
+            | EditorMode::ReplaceFrom { from: ref mut input, .. }
+            | EditorMode::ReplaceTo { to: ref mut input, .. } => Some(input),
+            _ => None,
+        }
+    }
+
+    /// Sets the columns between tab stops in the current buffer. An empty
+    /// width keeps the current one.
+    pub fn set_tab_width(&mut self, width: &str) {
+        self.mode = EditorMode::Normal;
+        if width.is_empty() {
+            return;
+        }
+        match width.trim().parse::<usize>() {
+            Ok(width @ 1..=MAX_TAB_WIDTH) => {
+                self.buffer_mut().tab_width = width;
+                self.message = Some(format!("Tab width set to {width}"));
+            }
+            _ => self.message = Some(format!("Tab width must be a number from 1 to {MAX_TAB_WIDTH}")),
+        }
+    }
+
+    /// Switches the current buffer between wrapping long lines and
+    /// scrolling sideways to show them.
+    pub fn toggle_visual_line(&mut self) {
+        let buffer = self.buffer_mut();
+        buffer.visual_line = !buffer.visual_line;
+        let state = if buffer.visual_line { "enabled" } else { "disabled" };
+        self.message = Some(format!("Visual-Line mode {state} in current buffer"));
+    }
+
+    /// How many buffer lines `win` shows in full.
+    pub fn visible_lines(&self, win: &Window) -> usize {
+        let buffer = &self.buffers[win.buffer];
+        if !buffer.visual_line {
+            return win.height;
+        }
+        let width = wrap_width(win.width);
+        let mut rows = 0;
+        let mut lines = 0;
+        for line in win.row_offset..buffer.text.len_lines() {
+            rows += buffer.text.wrap_line(line, width, buffer.tab_width).len();
+            if rows > win.skip_rows + win.height {
+                break;
+            }
+            lines += 1;
+        }
+        lines
+    }
+
+    /// Switches highlighting of the active region on or off everywhere.
+    /// Region commands work on the region either way.
+    pub fn toggle_transient_mark(&mut self) {
+        self.transient_mark = !self.transient_mark;
+        let state = if self.transient_mark { "enabled" } else { "disabled" };
+        self.message = Some(format!("Transient Mark mode {state}"));
+    }
+
+    /// Switches the current buffer between indenting with tabs and with
+    /// spaces.
+    pub fn toggle_indent_tabs(&mut self) {
+        let buffer = self.buffer_mut();
+        buffer.indent_tabs = !buffer.indent_tabs;
+        let state = if buffer.indent_tabs { "enabled" } else { "disabled" };
+        self.message = Some(format!("Indent-tabs mode {state} in current buffer"));
+    }
+
+    /// Writes every buffer with unsaved edits to its auto-save file.
+    pub fn auto_save(&mut self) {
+        self.keys_since_auto_save = 0;
+        for buffer in &mut self.buffers {
+            if let Err(e) = buffer.auto_save() {
+                self.message = Some(format!("Auto-saving {}: {e}", buffer.name));
+            }
+        }
+    }
+
+    /// Offers to recover the first buffer from `from` on whose auto-save file
+    /// is newer than its file, showing how the two differ.
+    pub fn offer_recovery(&mut self, from: usize) {
+        let Some((idx, contents)) =
+            (from..self.buffers.len()).find_map(|idx| Some((idx, self.buffers[idx].newer_auto_save()?)))
+        else {
+            return;
+        };
+        let buffer = &self.buffers[idx];
+        let auto_save = buffer.auto_save_path().unwrap_or_default();
+        let text = buffer.text.to_string();
+        let old: Vec<&str> = text.split('\n').collect();
+        let new: Vec<&str> = contents.split('\n').collect();
+        let diff = diff::unified(&buffer.filename, &auto_save.to_string_lossy(), &old, &new);
+
+        let mut diff_buffer = Buffer::new(DIFF.to_string());
+        diff_buffer.text = Text::from(diff.trim_end_matches('\n'));
+        diff_buffer.read_only = true;
+        self.add_buffer(diff_buffer);
+        self.mode = EditorMode::ConfirmRecover(idx);
+    }
+
+    /// Answers the question asked by `offer_recovery` for buffer `idx`, then
+    /// moves on to the next buffer with auto-save data.
+    pub fn recover_file(&mut self, idx: usize, recover: bool) {
+        self.mode = EditorMode::Normal;
+        // The diff buffer was added last and is current
+        self.kill_buffer(self.cur_buf);
+        self.switch_to_buffer(idx);
+        match self.buffer().newer_auto_save() {
+            Some(contents) if recover => {
+                let point = self.point();
+                let buffer = self.buffer_mut();
+                let len = buffer.text.len_chars();
+                buffer.delete(0, len, point);
+                buffer.insert(0, &contents, 0, false);
+                self.set_point(0);
+                self.message = Some("Auto-save file recovered; C-x C-s to keep it".to_string());
+            }
+            _ => self.message = Some("Recover-file canceled".to_string()),
+        }
+        self.offer_recovery(idx + 1);
+    }
+
+    pub fn kill_buffer(&mut self, idx: usize) {
+        self.mode = EditorMode::Normal;
+        let name = self.buffers[idx].name.clone();
+        if self.buffers.len() == 1 {
+            self.buffers.push(Buffer::new(SCRATCH.to_string()));
+        }
+        if idx == self.cur_buf {
+            let other = if self.last_buf != idx && self.last_buf < self.buffers.len() {
+                self.last_buf
+            } else if idx == 0 {
+                1
+            } else {
+                idx - 1
+            };
+            self.switch_to_buffer(other);
+        }
+        let cur_buf = self.cur_buf;
+        for win in &mut self.windows {
+            if win.buffer == idx {
+                win.buffer = cur_buf;
+            }
+        }
+        self.buffers.remove(idx);
+        for win in &mut self.windows {
+            if win.buffer > idx {
+                win.buffer -= 1;
+            }
+        }
+        if self.cur_buf > idx {
+            self.cur_buf -= 1;
+        }
+        if self.last_buf >= idx && self.last_buf > 0 {
+            self.last_buf -= 1;
+        }
+        self.message = Some(format!("Killed buffer {name}"));
+    }
+
+    /// Shows a buffer listing every buffer's name, size, state and file.
+    pub fn list_buffers(&mut self) {
+        let mut list = format!(
+            " CMR {:<20} {:>5}  File\n --- {:<20} {:>5}  ----",
+            "Buffer", "Size", "------", "----",
+        );
+        for (i, b) in self.buffers.iter().enumerate() {
+            if b.name == BUFFER_LIST {
+                continue;
+            }
+            list.push_str(&format!(
+                "\n {}{}{} {:<20} {:>5}  {}",
+                if i == self.cur_buf { '.' } else { ' ' },
+                if b.modified { '*' } else { ' ' },
+                if b.read_only { '%' } else { ' ' },
+                b.name,
+                b.text.len_chars(),
+                b.filename,
+            ));
+        }
+
+        let idx = match self.find_buffer(BUFFER_LIST) {
+            Some(idx) => idx,
+            None => {
+                self.buffers.push(Buffer::new(BUFFER_LIST.to_string()));
+                self.buffers.len() - 1
+            }
+        };
+        self.buffers[idx] = Buffer {
+            text: Text::from(list.as_str()),
+            read_only: true,
+            ..Buffer::new(BUFFER_LIST.to_string())
+        };
+        self.switch_to_buffer(idx);
+        self.set_point(0);
+    }
+
+    /// Makes window `idx` the selected window.
+    pub fn select_window(&mut self, idx: usize) {
+        self.sync_window();
+        self.cur_win = idx;
+        let win = &self.windows[idx];
+        self.cur_buf = win.buffer;
+        self.cur_x = win.cur_x;
+        self.cur_y = win.cur_y;
+        self.row_offset = win.row_offset;
+        self.col_offset = win.col_offset;
+    }
+
+    /// Scrolls the text of the selected window left (C-x <) or right
+    /// (C-x >) by nearly its width, moving the cursor along if it would go
+    /// out of view.
+    pub fn scroll_horizontally(&mut self, left: bool) {
+        let win = &mut self.windows[self.cur_win];
+        let width = win.width;
+        let amount = width.saturating_sub(2).max(1);
+        self.col_offset = if left { self.col_offset + amount } else { self.col_offset.saturating_sub(amount) };
+        win.min_col_offset = self.col_offset;
+
+        let tab_width = self.buffer().tab_width;
+        let x = self.text().display_col(self.cur_y, self.cur_x, tab_width);
+        if self.col_offset > 0 && x <= self.col_offset {
+            self.cur_x = self.text().col_at_display(self.cur_y, self.col_offset + 1, tab_width);
+        } else if x + 2 > self.col_offset + width {
+            self.cur_x = self.text().col_at_display(self.cur_y, (self.col_offset + width).saturating_sub(2), tab_width);
+        }
+    }
+
+    /// Splits the selected window in two showing the same buffer, one above
+    /// the other or side by side.
+    pub fn split_window(&mut self, vertical: bool) {
+        self.sync_window();
+        let win = &self.windows[self.cur_win];
+        let too_small = if vertical {
+            win.width < 2 * WINDOW_MIN_WIDTH + 1
+        } else {
+            win.height < 2 * WINDOW_MIN_HEIGHT + 1
+        };
+        if too_small {
+            self.message = Some(format!("Window #{} too small for splitting", self.cur_win + 1));
+            return;
+        }
+        let new = self.cur_win + 1;
+        self.windows.insert(new, win.clone());
+        self.layout.split(self.cur_win, new, vertical);
+    }
+
+    pub fn other_window(&mut self) {
+        self.select_window((self.cur_win + 1) % self.windows.len());
+    }
+
+    /// Selects another window, splitting the selected one if it is the
+    /// only one.
+    fn pop_to_other_window(&mut self) {
+        if self.windows.len() == 1 {
+            self.split_window(false);
+        }
+        if self.windows.len() > 1 {
+            self.other_window();
+        }
+    }
+
+    pub fn delete_window(&mut self) {
+        if self.windows.len() == 1 {
+            self.message = Some("Attempt to delete sole ordinary window".to_string());
+            return;
+        }
+        let idx = self.cur_win;
+        self.select_window(if idx == 0 { 1 } else { idx - 1 });
+        self.windows.remove(idx);
+        self.layout.remove(idx);
+        if self.cur_win > idx {
+            self.cur_win -= 1;
+        }
+    }
+
+    pub fn delete_other_windows(&mut self) {
+        self.sync_window();
+        let win = self.windows.swap_remove(self.cur_win);
+        self.windows = vec![win];
+        self.cur_win = 0;
+        self.layout = Layout::Window(0);
+    }
+
+    /// Inserts a tab, or spaces up to the next tab stop if the buffer does
+    /// not indent with tabs.
     pub fn insert_tab(&mut self) {
-        for _ in 0..TAB_WIDTH {
-            self.insert_char(' ');
+        if self.buffer().indent_tabs {
+            self.insert_str("\t");
+        } else {
+            let tab_width = self.buffer().tab_width;
+            let col = self.text().display_col(self.cur_y, self.cur_x, tab_width);
+            self.insert_str(&" ".repeat(tab_stop(col, tab_width) - col));
         }
     }
 
+    /// One level of indentation in the current buffer.
+    fn indent_unit(&self) -> String {
+        if self.buffer().indent_tabs { "\t".to_string() } else { " ".repeat(self.buffer().tab_width) }
+    }
+
     pub fn write_shell_cmd(&mut self) {
-        self.cmd = Command::None;
         self.mode = EditorMode::ShellCommand(String::new());
     }
 
     pub fn run_shell_cmd(&mut self, command: &str) -> io::Result<()> {
-        terminal::disable_raw_mode()?;
-        execute!(stdout(), terminal::LeaveAlternateScreen)?;
+        self.backend.leave()?;
 
-        let _ = ShellCommand::new("sh")
-            .arg("-c")
-            .arg(command)
-            .status();
+        // Standard input may be a pipe we already read, so talk to the
+        // terminal directly when there is one.
+        let tty = File::open("/dev/tty").ok();
+        let mut shell = ShellCommand::new("sh");
+        shell.arg("-c").arg(command);
+        if let Some(tty) = tty.as_ref().and_then(|tty| tty.try_clone().ok()) {
+            shell.stdin(tty);
+        }
+        let _ = shell.status();
 
         println!("(End)");
 
-        let _ = std::io::stdin().read_line(&mut String::new());
+        let _ = match tty {
+            Some(tty) => BufReader::new(tty).read_line(&mut String::new()),
+            None => std::io::stdin().read_line(&mut String::new()),
+        };
 
-        execute!(stdout(), terminal::EnterAlternateScreen)?;
-        terminal::enable_raw_mode()?;
-        execute!(
-            stdout(),
-            terminal::Clear(ClearType::All),
-            cursor::MoveTo(self.cur_x as u16, self.cur_y as u16)
-        )?;
-        self.last_frame.clear();
+        self.backend.enter()?;
         self.mode = EditorMode::Normal;
 
         Ok(())
     }
 } 
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Draws line `idx` scrolled `col_offset` columns to the left in the
+/// `width` columns from `x` on row `y`, with the part inside the
+/// `highlight` range in reverse video. A `$` in the first or last column
+/// marks text hidden off that edge.
+fn draw_line(
+    screen: &mut Screen,
+    (x, y, width): (usize, usize, usize),
+    buffer: &Buffer,
+    idx: usize,
+    col_offset: usize,
+    highlight: Option<(usize, usize)>,
+) {
+    if width == 0 {
+        return;
+    }
+    let (text, tab_width) = (&buffer.text, buffer.tab_width);
+    let line_len = text.line_len(idx);
+    let line_width = text.display_col(idx, line_len, tab_width);
+    let hidden_left = col_offset > 0 && line_width > 0;
+    let hidden_right = line_width > col_offset + width && width > hidden_left as usize;
+    // The line columns shown as text
+    let first = col_offset + hidden_left as usize;
+    let last = col_offset + width - hidden_right as usize;
+
+    if hidden_left {
+        screen.put_str(x, y, "$", false);
+    }
+    draw_span(screen, (x + hidden_left as usize, y), buffer, idx, first..last, line_len, highlight);
+    if hidden_right {
+        screen.put_str(x + width - 1, y, "$", false);
+    }
+}
+
+/// Draws the screen columns `shown` of line `idx`, up to column `end`, from
+/// column `x` of row `y`, with the part inside the `highlight` range in
+/// reverse video.
+fn draw_span(
+    screen: &mut Screen,
+    (x, y): (usize, usize),
+    buffer: &Buffer,
+    idx: usize,
+    shown: Range<usize>,
+    end: usize,
+    highlight: Option<(usize, usize)>,
+) {
+    let (text, tab_width) = (&buffer.text, buffer.tab_width);
+    let line_start = text.line_to_char(idx);
+    let line_end = line_start + text.line_len(idx);
+    let (start, stop) = highlight.unwrap_or((usize::MAX, usize::MAX));
+
+    let mut len = 0;
+    let mut col = 0;
+    let mut at = line_start;
+    for g in text.line(idx).graphemes(true) {
+        if at - line_start >= end {
+            break;
+        }
+        let g_width = grapheme_width(g, col, tab_width);
+        let reverse = (start..stop).contains(&at);
+        // Blank out what is visible of a wide character or tab cut by an edge
+        if col + g_width > shown.end {
+            screen.fill(x + len, x + shown.end.saturating_sub(shown.start), y, reverse);
+            return;
+        }
+        if col >= shown.start {
+            len += screen.put_str(x + len, y, &grapheme_text(g, col, tab_width), reverse);
+        } else if col + g_width > shown.start {
+            screen.fill(x + len, x + len + col + g_width - shown.start, y, reverse);
+            len += col + g_width - shown.start;
+        }
+        col += g_width;
+        at += g.chars().count();
+    }
+    // Show the region reaching past the end of the line as one cell
+    if start <= line_end && stop > line_end && at == line_end && shown.contains(&col) {
+        screen.fill(x + len, x + len + 1, y, true);
+    }
+}
+
+/// Columns lines wrap at in a window `width` columns wide, leaving the last
+/// column for the `\` marking a continued line.
+fn wrap_width(width: usize) -> usize {
+    width.saturating_sub(1).max(1)
+}
+
+/// The screen row of wrapped line `idx` that column `col` is on, and its
+/// screen column within the row.
+fn wrapped_pos(text: &Text, idx: usize, col: usize, width: usize, tab_width: usize) -> (usize, usize) {
+    let rows = text.wrap_line(idx, width, tab_width);
+    let row = rows.iter().rposition(|&(start, _)| start <= col).unwrap_or(0);
+    (row, text.display_col(idx, col, tab_width) - rows[row].1)
+}
+
+/// Draws `win` in visual-line mode, with long lines wrapped and each row
+/// that continues on the next ending in `\`.
+fn draw_wrapped(screen: &mut Screen, buffer: &Buffer, win: &Window, highlight: Option<(usize, usize)>) {
+    let (text, tab_width) = (&buffer.text, buffer.tab_width);
+    let width = wrap_width(win.width);
+    let mut y = win.top;
+    let mut skip = win.skip_rows;
+    for idx in win.row_offset.. {
+        if y >= win.top + win.height {
+            break;
+        }
+        if idx >= text.len_lines() {
+            screen.put_str(win.left, y, "~", false);
+            y += 1;
+            continue;
+        }
+        let line_len = text.line_len(idx);
+        let rows = text.wrap_line(idx, width, tab_width);
+        for (r, &(_, x)) in rows.iter().enumerate().skip(skip) {
+            if y >= win.top + win.height {
+                break;
+            }
+            let end = rows.get(r + 1).map_or(line_len, |&(next, _)| next);
+            draw_span(screen, (win.left, y), buffer, idx, x..x + width, end, highlight);
+            if win.width > width && end < line_len {
+                screen.put_str(win.left + width, y, "\\", false);
+            }
+            y += 1;
+        }
+        skip = 0;
+    }
+}
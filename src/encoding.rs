@@ -0,0 +1,265 @@
+use std::fmt;
+
+/// The characters Windows-1252 puts at 0x80-0x9F. The five unassigned bytes
+/// map to the C1 controls with the same value, as in Latin-1, so any byte
+/// round-trips.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// How a buffer's text is stored on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// UTF-8 starting with a byte order mark.
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Windows1252,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 6] = [
+        Encoding::Utf8,
+        Encoding::Utf8Bom,
+        Encoding::Utf16Le,
+        Encoding::Utf16Be,
+        Encoding::Latin1,
+        Encoding::Windows1252,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin-1",
+            Encoding::Windows1252 => "windows-1252",
+        }
+    }
+
+    /// Looks up an encoding by name, ignoring case and accepting a few
+    /// common aliases.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_ascii_lowercase();
+        let encoding = match name.as_str() {
+            "utf8" => Encoding::Utf8,
+            "utf-8-with-signature" => Encoding::Utf8Bom,
+            "latin1" | "iso-8859-1" | "iso-latin-1" => Encoding::Latin1,
+            "cp1252" => Encoding::Windows1252,
+            _ => return Self::ALL.into_iter().find(|e| e.name() == name),
+        };
+        Some(encoding)
+    }
+
+    /// Guesses the encoding of `bytes`: a byte order mark wins, then UTF-8
+    /// if the bytes are valid, then Windows-1252 if they use its extra
+    /// characters, and Latin-1 otherwise.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(UTF8_BOM) {
+            Encoding::Utf8Bom
+        } else if bytes.starts_with(UTF16LE_BOM) {
+            Encoding::Utf16Le
+        } else if bytes.starts_with(UTF16BE_BOM) {
+            Encoding::Utf16Be
+        } else if std::str::from_utf8(bytes).is_ok() {
+            Encoding::Utf8
+        } else if bytes.iter().any(|b| (0x80..0xA0).contains(b)) {
+            Encoding::Windows1252
+        } else {
+            Encoding::Latin1
+        }
+    }
+
+    /// Decodes `bytes`, dropping any byte order mark. Invalid sequences
+    /// become U+FFFD.
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Encoding::Utf8Bom => {
+                String::from_utf8_lossy(bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes)).into_owned()
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let bom = if self == Encoding::Utf16Le { UTF16LE_BOM } else { UTF16BE_BOM };
+                let bytes = bytes.strip_prefix(bom).unwrap_or(bytes);
+                let units = bytes.chunks(2).map(|pair| match (pair, self) {
+                    ([lo, hi], Encoding::Utf16Le) | ([hi, lo], Encoding::Utf16Be) => {
+                        u16::from_le_bytes([*lo, *hi])
+                    }
+                    // A dangling odd byte
+                    _ => 0xFFFD,
+                });
+                char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            }
+            Encoding::Latin1 => bytes.iter().map(|&b| char::from(b)).collect(),
+            Encoding::Windows1252 => bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..0xA0 => WINDOWS_1252_HIGH[b as usize - 0x80],
+                    _ => char::from(b),
+                })
+                .collect(),
+        }
+    }
+
+    /// Encodes `s`, adding a byte order mark if the encoding has one. Fails
+    /// with the first character the encoding cannot represent.
+    pub fn encode(self, s: &str) -> Result<Vec<u8>, char> {
+        match self {
+            Encoding::Utf8 => Ok(s.as_bytes().to_vec()),
+            Encoding::Utf8Bom => Ok([UTF8_BOM, s.as_bytes()].concat()),
+            Encoding::Utf16Le => {
+                let units = s.encode_utf16().flat_map(u16::to_le_bytes);
+                Ok(UTF16LE_BOM.iter().copied().chain(units).collect())
+            }
+            Encoding::Utf16Be => {
+                let units = s.encode_utf16().flat_map(u16::to_be_bytes);
+                Ok(UTF16BE_BOM.iter().copied().chain(units).collect())
+            }
+            Encoding::Latin1 => s.chars().map(|c| u8::try_from(c).map_err(|_| c)).collect(),
+            Encoding::Windows1252 => s
+                .chars()
+                .map(|c| match WINDOWS_1252_HIGH.iter().position(|&h| h == c) {
+                    Some(i) => Ok(0x80 + i as u8),
+                    None if (0x80..0xA0).contains(&(c as u32)) => Err(c),
+                    None => u8::try_from(c).map_err(|_| c),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How lines end in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /// The suffix Emacs gives coding systems using this line ending.
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "unix",
+            LineEnding::CrLf => "dos",
+            LineEnding::Cr => "mac",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [LineEnding::Lf, LineEnding::CrLf, LineEnding::Cr]
+            .into_iter()
+            .find(|eol| eol.name() == name)
+    }
+
+    /// CRLF if every line feed follows a carriage return, CR if there are
+    /// carriage returns but no line feeds, and LF otherwise. Mixed files
+    /// come out as LF so that no carriage return is lost on save.
+    pub fn detect(s: &str) -> Self {
+        let lf = s.matches('\n').count();
+        if lf > 0 && s.matches("\r\n").count() == lf {
+            LineEnding::CrLf
+        } else if lf == 0 && s.contains('\r') {
+            LineEnding::Cr
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+/// Everything about how a buffer maps to the bytes of its file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileFormat {
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+    /// Whether the last line ends with a line ending.
+    pub final_newline: bool,
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        Self { encoding: Encoding::default(), line_ending: LineEnding::default(), final_newline: true }
+    }
+}
+
+impl FileFormat {
+    /// Splits `bytes` into text with lines joined by '\n' and the format
+    /// needed to write it back unchanged.
+    pub fn decode(bytes: &[u8]) -> (String, Self) {
+        let encoding = Encoding::detect(bytes);
+        let content = encoding.decode(bytes);
+        let line_ending = LineEnding::detect(&content);
+        let mut content = match line_ending {
+            LineEnding::Lf => content,
+            eol => content.replace(eol.as_str(), "\n"),
+        };
+        let final_newline = content.ends_with('\n');
+        if final_newline {
+            content.pop();
+        }
+        (content, Self { encoding, line_ending, final_newline })
+    }
+
+    /// This format changed as `name` asks: an encoding (`latin-1`), a line
+    /// ending (`dos`) or both (`latin-1-dos`).
+    pub fn with_name(self, name: &str) -> Option<Self> {
+        let name = name.trim().to_ascii_lowercase();
+        if let Some(line_ending) = LineEnding::from_name(&name) {
+            return Some(Self { line_ending, ..self });
+        }
+        if let Some((encoding, eol)) = name.rsplit_once('-')
+            && let (Some(encoding), Some(line_ending)) = (Encoding::from_name(encoding), LineEnding::from_name(eol))
+        {
+            return Some(Self { encoding, line_ending, ..self });
+        }
+        Encoding::from_name(&name).map(|encoding| Self { encoding, ..self })
+    }
+
+    /// The inverse of `decode`. Fails with the first character the encoding
+    /// cannot represent.
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, char> {
+        let mut content = match self.line_ending {
+            LineEnding::Lf => text.to_string(),
+            eol => text.replace('\n', eol.as_str()),
+        };
+        if self.final_newline {
+            content.push_str(self.line_ending.as_str());
+        }
+        self.encoding.encode(&content)
+    }
+}
+
+/// Shown as `encoding-eol`, the way Emacs names coding systems.
+impl fmt::Display for FileFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.encoding, self.line_ending.name())
+    }
+}
@@ -1,274 +1,94 @@
-use crossterm::{
-    event::{self, Event, KeyCode, KeyModifiers, KeyEvent},
-};
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
 
+use crate::commands;
 use crate::editor::Editor;
-use crate::editor::Command;
+use crate::editor::Action;
 use crate::editor::EditorMode;
-use crate::buffer::save_buffer;
-use crate::ctrl;
-use crate::alt;
-use crate::alt_ctrl;
+use crate::keymap::{keys_to_string, Binding, Key};
 
 pub fn get_inputs(editor: &mut Editor) -> Result<bool, std::io::Error> {
-    let mut check = false;
-    if let Event::Key(key_event) = event::read()? {
-        match editor.mode {
-            EditorMode::SaveFile => {
-                check = check_keys_save(editor, key_event);
-            }
-            EditorMode::Normal => {
-                check = check_keys_normal(editor, key_event);
-            }
-            EditorMode::PromptQuit => {
-                match key_event.code {
-                    KeyCode::Char('y') => return Ok(true),
-                    KeyCode::Char('n') | KeyCode::Esc => {
-                        editor.mode = EditorMode::Normal;
-                    }
-                    _ => {}
-                }
-            }
-            EditorMode::ShellCommand(ref mut cmd) => {
-                match key_event.code {
-                    KeyCode::Char(c) => {
-                        cmd.push(c);
-                    }
-                    KeyCode::Backspace => {
-                        cmd.pop();
-                    }
-                    KeyCode::Enter => {
-                        let command = std::mem::take(cmd);
-                        editor.mode = EditorMode::Normal;
-                        let _ = editor.run_shell_cmd(&command);
-                    }
-                    KeyCode::Esc => {
-                        editor.mode = EditorMode::Normal;
-                    }
-                    _ => {}
-                }
-            }
-        }
-    }
-    Ok(check)
+    Ok(handle_event(editor, event::read()?))
 }
 
-fn check_keys_save(editor: &mut Editor, key_event: KeyEvent) -> bool {
-    match key_event.code {
-        KeyCode::Enter => {
-            if editor.filename.is_empty() {
-                // Nothing has been entered, so continue to wait for user to 
-                // enter a filename.
-            } else {
-                match save_buffer(&editor.buf, &editor.filename) {
-                    Ok(0) => {
-
-                    }
-                    Ok(n) => {
-                        editor.modified = false;
-                        editor.message = Some(format!("(Wrote {} line{})", n, if n == 1 { "" } else { "s" }));
-                    }
-                    Err(e) => {
-                        eprintln!("Error saving file: {e}");
-                    }
-                }
-                editor.mode = EditorMode::Normal;
-            }
-        }
-
-        KeyCode::Esc => {
-            editor.filename.clear();
-            editor.mode = EditorMode::Normal;
-        }
-
-        KeyCode::Backspace => {
-            editor.filename.pop();
-        }
-
-        KeyCode::Char(c) => {
-            editor.filename.push(c);
-        }
-        
-        _ => {}
+/// Acts on one terminal event. Returns whether the editor should exit.
+pub fn handle_event(editor: &mut Editor, event: Event) -> bool {
+    match event {
+        Event::Resize(..) => {
+            // The next render lays the windows out for the new size and
+            // repaints the whole screen
+            editor.backend.invalidate();
+            false
+        }
+        Event::Key(key_event) => handle_key(editor, key_event.into()),
+        _ => false,
     }
-    false
 }
 
-fn check_keys_normal(editor: &mut Editor, key_event: KeyEvent) -> bool {
-    editor.message = None;
-    match key_event.code {
-        _ if ctrl!('x', key_event) => {
-            editor.cmd = Command::CtrlX;             
-        }
-
-        // New line
-        _ if ctrl!('m', key_event) => {
-
-        }
-
-        _ if ctrl!('a', key_event) => {
-            editor.move_to_line_start(); 
-        }
-
-        _ if ctrl!('e', key_event) => {
-            editor.move_to_line_end();
-        }
-
-        _ if ctrl!('n', key_event) => {
-            editor.move_next_line();
-        }
-
-        _ if ctrl!('p', key_event) => {
-            editor.move_prev_line();
-        }
-
-        _ if ctrl!('z', key_event) => {
-            editor.move_prev_page();
-        }
-
-        _ if ctrl!('v', key_event) => {
-            editor.move_next_page();
-        }
-
-        _ if ctrl!('k', key_event) => {
-            editor.kill_to_eol();
-        }
-
-        _ if ctrl!('h', key_event) => {
-            editor.del_prev_char();
-        }
-
-        _ if ctrl!('d', key_event) => {
-            editor.del_next_char();
-        }
-
-        _ if alt!('d', key_event) => {
-            editor.del_next_word();
-        }
-
-        _ if alt_ctrl!('h', key_event) => {
-            editor.del_prev_word();
-        }
+/// Looks `key` up, after any prefix keys already typed, in the keymap for
+/// the editor's mode and runs the command it completes. Returns whether the
+/// editor should exit.
+pub fn handle_key(editor: &mut Editor, key: Key) -> bool {
+    if editor.mode == EditorMode::Normal && editor.keys.is_empty() {
+        editor.message = None;
+        editor.buffer_mut().undo.boundary();
+        editor.last_action = std::mem::replace(&mut editor.action, Action::Other);
+    }
 
-        _ if alt!('z', key_event) => {
-            return editor.quick_exit();
+    editor.keys.push(key);
+    match editor.keymaps.for_mode(&editor.mode).lookup(&editor.keys) {
+        Some(Binding::Prefix(_)) => false,
+        Some(&Binding::Command(command)) => {
+            editor.keys.clear();
+            command.run(editor)
         }
-
-        // Run shell command
-        KeyCode::Char('!') => {
-            if editor.cmd == Command::CtrlX {
-                editor.write_shell_cmd();
-            } else {
-                editor.insert_char('!');
-            }
-        }
-
-        // Save file
-        KeyCode::Char('d') => {
-            if editor.cmd == Command::CtrlX {
-                editor.save_file();
-            } else {
-                editor.insert_char('d');
-            }
-        }
-        
-        // Quit
-        KeyCode::Char('c') => {
-            if editor.cmd == Command::CtrlX {
-                return editor.quit();
-            } else {
-                editor.insert_char('c');
-            }
-        }
-
-        // Write file
-        KeyCode::Char('w') => {
-            if editor.cmd == Command::CtrlX {
-                editor.write_buffer();
-            } else {
-                editor.insert_char('w');
-            }
+        None => {
+            let keys = std::mem::take(&mut editor.keys);
+            unbound(editor, keys)
         }
+    }
+}
 
-        // Incremental search
-        KeyCode::Char('s') => {
-            if editor.cmd == Command::CtrlX {
-                        
+/// Handles a key sequence bound to nothing: characters are typed into the
+/// buffer or prompt, and any other key ends an incremental search or a
+/// query-replace and runs as usual. Prompts ignore other keys, so commands
+/// that edit the buffer cannot run while one is open.
+fn unbound(editor: &mut Editor, keys: Vec<Key>) -> bool {
+    let typed = match keys[..] {
+        [key] => key.plain_char(),
+        _ => None,
+    };
+    match editor.mode {
+        EditorMode::Normal => {
+            if let Some(c) = typed {
+                editor.insert_char(c);
+            } else if keys.last() == Some(&Key::new(KeyCode::Char('g'), KeyModifiers::CONTROL)) {
+                editor.keyboard_quit();
             } else {
-                editor.insert_char('s');
+                editor.message = Some(format!("{} is undefined", keys_to_string(&keys)));
             }
         }
-
-        KeyCode::Tab => {
-            editor.insert_tab();
-        }
-
-        KeyCode::Char(c) => {
-            if editor.cmd == Command::CtrlX {
-                editor.message = Some(format!("(Key not bound)"));
-                editor.cmd = Command::None;
+        EditorMode::ISearch(_) | EditorMode::QueryReplace(_) => {
+            if let EditorMode::ISearch(_) = editor.mode {
+                if let Some(c) = typed {
+                    commands::isearch_printing_char(editor, c);
+                    return false;
+                }
+                commands::isearch_exit(editor);
             } else {
-                editor.insert_char(c);
+                commands::replace_exit(editor);
             }
-        }
-
-        KeyCode::Enter => {
-            let current_line = editor.buf.get_mut(editor.cur_y).unwrap();
-            let new_line = current_line.split_off(editor.cur_x);
-            editor.buf.insert(editor.cur_y + 1, new_line);
-            editor.cur_y += 1;
-            editor.cur_x = 0;
-            editor.update_modified();
-        }
-
-        KeyCode::Backspace => {
-            if editor.cur_x > 0 {
-                editor.buf[editor.cur_y].remove(editor.cur_x - 1);
-                editor.cur_x -= 1;
-                editor.update_modified();
-            } else if editor.cur_y > 0 {
-                let prev_line_len = editor.buf[editor.cur_y - 1].len();
-                let current_line = editor.buf.remove(editor.cur_y);
-                editor.cur_y -= 1;
-                editor.cur_x = prev_line_len;
-                editor.buf[editor.cur_y].push_str(&current_line);
-                editor.update_modified();
-            }
-        }
-
-        KeyCode::Left => {
-            if editor.cur_x > 0 {
-                editor.cur_x -= 1;
-            } else if editor.cur_y > 0 {
-                editor.cur_y -= 1;
-                editor.cur_x = editor.buf[editor.cur_y].len();
-            }
-        }
-
-        KeyCode::Right => {
-            if editor.cur_x < editor.buf[editor.cur_y].len() {
-                editor.cur_x += 1;
-            } else if editor.cur_y + 1 < editor.buf.len() {
-                editor.cur_y += 1;
-                editor.cur_x = 0;
-            }
-        }
-
-        KeyCode::Up => {
-            if editor.cur_y > 0 {
-                editor.cur_y -= 1;
-                editor.cur_x = editor.cur_x.min(editor.buf[editor.cur_y].len());
-            }
-        }
-        
-        KeyCode::Down => {
-            if editor.cur_y + 1 < editor.buf.len() {
-                editor.cur_y += 1;
-                editor.cur_x = editor.cur_x.min(editor.buf[editor.cur_y].len());
+            let mut exit = false;
+            for key in keys {
+                exit = handle_key(editor, key);
             }
+            return exit;
         }
         _ => {
-            editor.cmd = Command::None;
+            if let Some(c) = typed
+                && let Some(input) = editor.prompt_input()
+            {
+                input.push(c);
+            }
         }
     }
     false
@@ -0,0 +1,508 @@
+//! Key sequences bound to named commands.
+//!
+//! Keys are written the Emacs way, as in `C-x 4 b` or `C-M-%`. A keymap
+//! binds single keys to commands or to nested keymaps for prefix keys such
+//! as `C-x` and `M-g`. `C-c` followed by a letter is left free for users'
+//! own bindings, which can be made in a file read at startup:
+//!
+//! ```text
+//! # Lines are commands; those starting with # are comments
+//! global-set-key C-c g goto-line
+//! global-unset-key C-z
+//! define-key isearch C-o isearch-exit
+//! ```
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::commands::{self, Command};
+use crate::editor::EditorMode;
+
+/// Keys with special names, as written in key sequences.
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("RET", KeyCode::Enter),
+    ("TAB", KeyCode::Tab),
+    ("SPC", KeyCode::Char(' ')),
+    ("DEL", KeyCode::Backspace),
+    ("ESC", KeyCode::Esc),
+    ("<left>", KeyCode::Left),
+    ("<right>", KeyCode::Right),
+    ("<up>", KeyCode::Up),
+    ("<down>", KeyCode::Down),
+    ("<home>", KeyCode::Home),
+    ("<end>", KeyCode::End),
+    ("<prior>", KeyCode::PageUp),
+    ("<next>", KeyCode::PageDown),
+    ("<insert>", KeyCode::Insert),
+    ("<delete>", KeyCode::Delete),
+    ("<backtab>", KeyCode::BackTab),
+];
+
+const GLOBAL: &[(&str, &str)] = &[
+    ("C-g", "keyboard-quit"),
+    // C-SPC and C-@ both arrive as C-SPC on most terminals
+    ("C-SPC", "set-mark-command"),
+    ("C-@", "set-mark-command"),
+    ("C-a", "move-beginning-of-line"),
+    ("C-e", "move-end-of-line"),
+    ("C-f", "forward-char"),
+    ("C-b", "backward-char"),
+    ("C-n", "next-line"),
+    ("C-p", "previous-line"),
+    ("<right>", "forward-char"),
+    ("<left>", "backward-char"),
+    ("<down>", "next-line"),
+    ("<up>", "previous-line"),
+    ("C-v", "scroll-up-command"),
+    ("C-z", "scroll-down-command"),
+    ("RET", "newline"),
+    ("C-m", "newline"),
+    ("TAB", "indent-for-tab-command"),
+    ("DEL", "delete-backward-char"),
+    ("C-h", "delete-backward-char"),
+    ("C-d", "delete-char"),
+    ("M-d", "kill-word"),
+    ("C-M-h", "backward-kill-word"),
+    ("C-k", "kill-line"),
+    ("C-w", "kill-region"),
+    ("M-w", "kill-ring-save"),
+    ("C-y", "yank"),
+    ("M-y", "yank-pop"),
+    // Terminals send C-_ as C-7
+    ("C-_", "undo"),
+    ("C-7", "undo"),
+    ("C-/", "undo"),
+    ("C-M-_", "undo-redo"),
+    ("C-M-7", "undo-redo"),
+    ("M-_", "undo-redo"),
+    ("C-s", "isearch-forward"),
+    ("C-r", "isearch-backward"),
+    ("M-%", "query-replace"),
+    ("C-M-%", "query-replace-regexp"),
+    // C-M-\ arrives as C-M-4
+    ("C-M-\\", "indent-region"),
+    ("C-M-4", "indent-region"),
+    ("M-z", "quick-exit"),
+    ("M-g g", "goto-line"),
+    ("M-g M-g", "goto-line"),
+    ("C-x C-x", "exchange-point-and-mark"),
+    ("C-x C-s", "save-buffer"),
+    ("C-x d", "save-buffer"),
+    ("C-x C-w", "write-file"),
+    ("C-x w", "write-file"),
+    ("C-x C-c", "save-buffers-kill-terminal"),
+    ("C-x c", "save-buffers-kill-terminal"),
+    ("C-x C-f", "find-file"),
+    ("C-x C-b", "list-buffers"),
+    ("C-x b", "switch-to-buffer"),
+    ("C-x k", "kill-buffer"),
+    ("C-x C-q", "read-only-mode"),
+    ("C-x C-u", "upcase-region"),
+    ("C-x C-l", "downcase-region"),
+    ("C-x TAB", "indent-region"),
+    ("C-x u", "undo"),
+    ("C-x s", "isearch-forward"),
+    // Terminals rarely send C-M-%
+    ("C-x %", "query-replace-regexp"),
+    ("C-x !", "shell-command"),
+    ("C-x 0", "delete-window"),
+    ("C-x 1", "delete-other-windows"),
+    ("C-x 2", "split-window-below"),
+    ("C-x 3", "split-window-right"),
+    ("C-x o", "other-window"),
+    ("C-x <", "scroll-left"),
+    ("C-x >", "scroll-right"),
+    ("C-x 4 b", "switch-to-buffer-other-window"),
+    ("C-x 4 f", "find-file-other-window"),
+    ("C-x 4 C-f", "find-file-other-window"),
+    ("C-x RET f", "set-buffer-file-coding-system"),
+    ("C-x x g", "revert-buffer"),
+    ("C-x x TAB", "indent-tabs-mode"),
+    ("C-x x w", "set-tab-width"),
+    ("C-x x v", "visual-line-mode"),
+    // Emacs leaves it unbound
+    ("C-x x m", "transient-mark-mode"),
+];
+
+const ISEARCH: &[(&str, &str)] = &[
+    ("C-s", "isearch-repeat-forward"),
+    ("C-r", "isearch-repeat-backward"),
+    ("M-p", "isearch-ring-retreat"),
+    ("M-n", "isearch-ring-advance"),
+    ("DEL", "isearch-delete-char"),
+    ("RET", "isearch-exit"),
+    ("C-g", "isearch-abort"),
+    ("ESC", "isearch-abort"),
+];
+
+const QUERY_REPLACE: &[(&str, &str)] = &[
+    ("y", "replace-act"),
+    ("SPC", "replace-act"),
+    ("n", "replace-skip"),
+    ("DEL", "replace-skip"),
+    ("<delete>", "replace-skip"),
+    (".", "replace-act-and-exit"),
+    ("!", "replace-automatic"),
+    ("q", "replace-exit"),
+    ("RET", "replace-exit"),
+    ("ESC", "replace-exit"),
+    ("C-g", "replace-exit"),
+];
+
+const MINIBUFFER: &[(&str, &str)] = &[
+    ("RET", "exit-minibuffer"),
+    ("TAB", "minibuffer-complete"),
+    ("DEL", "minibuffer-delete-backward-char"),
+    ("C-g", "minibuffer-keyboard-quit"),
+    ("ESC", "minibuffer-keyboard-quit"),
+];
+
+const Y_OR_N: &[(&str, &str)] = &[
+    ("y", "answer-yes"),
+    ("n", "answer-no"),
+    ("ESC", "answer-no"),
+    ("C-g", "answer-no"),
+    // Lets the differences offered for recovery be scrolled through first
+    ("C-v", "scroll-up-command"),
+    ("C-z", "scroll-down-command"),
+];
+
+/// A key with its modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    /// Shift is dropped from characters, which carry it in their case.
+    pub fn new(code: KeyCode, mut modifiers: KeyModifiers) -> Self {
+        if matches!(code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code, modifiers }
+    }
+
+    /// The character typed, if this is one without modifiers.
+    pub fn plain_char(&self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(c) if self.modifiers.is_empty() => Some(c),
+            _ => None,
+        }
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        while rest.len() > 2 {
+            let (modifier, after) = match rest.split_at_checked(2) {
+                Some(("C-", after)) => (KeyModifiers::CONTROL, after),
+                Some(("M-", after)) => (KeyModifiers::ALT, after),
+                Some(("S-", after)) => (KeyModifiers::SHIFT, after),
+                _ => break,
+            };
+            modifiers |= modifier;
+            rest = after;
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match KEY_NAMES.iter().find(|(name, _)| *name == rest) {
+                Some(&(_, code)) => code,
+                None => match rest.strip_prefix("<f").and_then(|n| n.strip_suffix('>')).map(str::parse) {
+                    Some(Ok(n)) => KeyCode::F(n),
+                    _ => return Err(format!("Invalid key: {s}")),
+                },
+            },
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, prefix) in
+            [(KeyModifiers::CONTROL, "C-"), (KeyModifiers::ALT, "M-"), (KeyModifiers::SHIFT, "S-")]
+        {
+            if self.modifiers.contains(modifier) {
+                f.write_str(prefix)?;
+            }
+        }
+        match (self.code, KEY_NAMES.iter().find(|(_, code)| *code == self.code)) {
+            (_, Some((name, _))) => f.write_str(name),
+            (KeyCode::Char(c), None) => write!(f, "{c}"),
+            (KeyCode::F(n), None) => write!(f, "<f{n}>"),
+            (code, None) => write!(f, "<{code:?}>"),
+        }
+    }
+}
+
+/// Parses a key sequence such as `C-x 4 b`.
+pub fn parse_keys(keys: &str) -> Result<Vec<Key>, String> {
+    let keys = keys.split_whitespace().map(str::parse).collect::<Result<Vec<Key>, _>>()?;
+    if keys.is_empty() {
+        return Err("Empty key sequence".to_string());
+    }
+    Ok(keys)
+}
+
+/// Shows a key sequence the way it is written.
+pub fn keys_to_string(keys: &[Key]) -> String {
+    keys.iter().map(Key::to_string).collect::<Vec<_>>().join(" ")
+}
+
+/// What a key is bound to.
+#[derive(Debug, Clone)]
+pub enum Binding {
+    Command(&'static Command),
+    /// A prefix key, whose next key is looked up in its own keymap.
+    Prefix(Keymap),
+}
+
+/// Keys bound to commands, or to keymaps of their own for prefix keys.
+#[derive(Debug, Clone, Default)]
+pub struct Keymap {
+    bindings: HashMap<Key, Binding>,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_bindings(bindings: &[(&str, &str)]) -> Self {
+        let mut keymap = Self::new();
+        for (keys, name) in bindings {
+            keymap.bind(keys, name).expect("default bindings are valid");
+        }
+        keymap
+    }
+
+    /// Binds the key sequence `keys` to the command called `name`, making
+    /// keymaps for its prefix keys as needed. A prefix key loses any command
+    /// it was bound to, and a key bound to a command loses its prefix map.
+    pub fn bind(&mut self, keys: &str, name: &str) -> Result<(), String> {
+        let command = commands::find(name).ok_or_else(|| format!("No command named {name}"))?;
+        let keys = parse_keys(keys)?;
+        let (last, prefix) = keys.split_last().expect("key sequences are not empty");
+        let mut keymap = self;
+        for key in prefix {
+            let binding = keymap.bindings.entry(*key).or_insert_with(|| Binding::Prefix(Keymap::new()));
+            if let Binding::Command(_) = binding {
+                *binding = Binding::Prefix(Keymap::new());
+            }
+            let Binding::Prefix(inner) = binding else { unreachable!() };
+            keymap = inner;
+        }
+        keymap.bindings.insert(*last, Binding::Command(command));
+        Ok(())
+    }
+
+    /// Removes the binding of the key sequence `keys`, with everything
+    /// under it if it is a prefix key.
+    pub fn unbind(&mut self, keys: &str) -> Result<(), String> {
+        let keys = parse_keys(keys)?;
+        let (last, prefix) = keys.split_last().expect("key sequences are not empty");
+        let keymap = if prefix.is_empty() {
+            Some(self)
+        } else {
+            match self.lookup_mut(prefix) {
+                Some(Binding::Prefix(keymap)) => Some(keymap),
+                _ => None,
+            }
+        };
+        keymap.and_then(|keymap| keymap.bindings.remove(last));
+        Ok(())
+    }
+
+    /// What the key sequence `keys` is bound to.
+    pub fn lookup(&self, keys: &[Key]) -> Option<&Binding> {
+        let (first, rest) = keys.split_first()?;
+        let binding = self.bindings.get(first)?;
+        match binding {
+            _ if rest.is_empty() => Some(binding),
+            Binding::Prefix(keymap) => keymap.lookup(rest),
+            Binding::Command(_) => None,
+        }
+    }
+
+    fn lookup_mut(&mut self, keys: &[Key]) -> Option<&mut Binding> {
+        let (first, rest) = keys.split_first()?;
+        let binding = self.bindings.get_mut(first)?;
+        match binding {
+            _ if rest.is_empty() => Some(binding),
+            Binding::Prefix(keymap) => keymap.lookup_mut(rest),
+            Binding::Command(_) => None,
+        }
+    }
+}
+
+/// The keymaps the editor looks keys up in: the global one, and those used
+/// in its place while searching, replacing or prompting.
+#[derive(Debug, Clone)]
+pub struct Keymaps {
+    pub global: Keymap,
+    pub isearch: Keymap,
+    pub query_replace: Keymap,
+    /// For prompts that read a line of text. Unbound characters are typed
+    /// into it.
+    pub minibuffer: Keymap,
+    /// For questions answered with y or n.
+    pub y_or_n: Keymap,
+}
+
+impl Keymaps {
+    /// The keymap keys are looked up in while in `mode`.
+    pub fn for_mode(&self, mode: &EditorMode) -> &Keymap {
+        match mode {
+            EditorMode::Normal => &self.global,
+            EditorMode::ISearch(_) => &self.isearch,
+            EditorMode::QueryReplace(_) => &self.query_replace,
+            EditorMode::PromptQuit
+            | EditorMode::ConfirmRecover(_)
+            | EditorMode::ConfirmSave
+            | EditorMode::ConfirmRevert
+            | EditorMode::ConfirmKill(_)
+            | EditorMode::ConfirmOverwrite(_) => &self.y_or_n,
+            _ => &self.minibuffer,
+        }
+    }
+
+    fn by_name(&mut self, name: &str) -> Option<&mut Keymap> {
+        match name {
+            "global" => Some(&mut self.global),
+            "isearch" => Some(&mut self.isearch),
+            "query-replace" => Some(&mut self.query_replace),
+            "minibuffer" => Some(&mut self.minibuffer),
+            "y-or-n" => Some(&mut self.y_or_n),
+            _ => None,
+        }
+    }
+
+    /// Makes the bindings in `config`, which has a command on each line:
+    /// `global-set-key KEYS COMMAND`, `global-unset-key KEYS`, or
+    /// `define-key KEYMAP KEYS COMMAND` for the `global`, `isearch`,
+    /// `query-replace`, `minibuffer` or `y-or-n` keymap. Stops at the first
+    /// line in error, naming it.
+    pub fn load(&mut self, config: &str) -> Result<(), String> {
+        for (i, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.load_line(line).map_err(|e| format!("line {}: {e}", i + 1))?;
+        }
+        Ok(())
+    }
+
+    fn load_line(&mut self, line: &str) -> Result<(), String> {
+        let (directive, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = args.trim();
+        let (keymap, args) = match directive {
+            "global-set-key" | "global-unset-key" => (&mut self.global, args),
+            "define-key" => {
+                let (name, args) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+                let keymap = self.by_name(name).ok_or_else(|| format!("No keymap named {name}"))?;
+                (keymap, args.trim())
+            }
+            _ => return Err(format!("Unknown command {directive}")),
+        };
+        if directive == "global-unset-key" {
+            return keymap.unbind(args);
+        }
+        match args.rsplit_once(char::is_whitespace) {
+            Some((keys, name)) => keymap.bind(keys.trim(), name),
+            None => Err(format!("{directive} needs keys and a command")),
+        }
+    }
+}
+
+impl Default for Keymaps {
+    fn default() -> Self {
+        Self {
+            global: Keymap::with_bindings(GLOBAL),
+            isearch: Keymap::with_bindings(ISEARCH),
+            query_replace: Keymap::with_bindings(QUERY_REPLACE),
+            minibuffer: Keymap::with_bindings(MINIBUFFER),
+            y_or_n: Keymap::with_bindings(Y_OR_N),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(keymap: &Keymap, keys: &str) -> Option<&'static str> {
+        match keymap.lookup(&parse_keys(keys).unwrap()) {
+            Some(Binding::Command(command)) => Some(command.name),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn keys_parse_and_print_the_emacs_way() {
+        for s in ["C-x", "M-%", "C-M-\\", "RET", "SPC", "C--", "<f5>", "<left>", "C-M-h"] {
+            assert_eq!(s.parse::<Key>().unwrap().to_string(), s);
+        }
+        assert_eq!("C-a".parse::<Key>(), Ok(Key::new(KeyCode::Char('a'), KeyModifiers::CONTROL)));
+        assert_eq!(Key::new(KeyCode::Char('A'), KeyModifiers::SHIFT).plain_char(), Some('A'));
+        assert!("C-".parse::<Key>().is_err());
+        assert!("<nope>".parse::<Key>().is_err());
+        assert!(parse_keys(" ").is_err());
+    }
+
+    #[test]
+    fn prefix_keys_get_nested_keymaps() {
+        let mut keymap = Keymap::new();
+        keymap.bind("C-c a b", "forward-char").unwrap();
+        assert!(matches!(keymap.lookup(&parse_keys("C-c a").unwrap()), Some(Binding::Prefix(_))));
+        assert_eq!(command(&keymap, "C-c a b"), Some("forward-char"));
+        assert_eq!(command(&keymap, "C-c a b c"), None);
+
+        keymap.bind("C-c a", "backward-char").unwrap();
+        assert_eq!(command(&keymap, "C-c a"), Some("backward-char"));
+        assert_eq!(command(&keymap, "C-c a b"), None);
+
+        keymap.unbind("C-c a").unwrap();
+        assert!(keymap.lookup(&parse_keys("C-c a").unwrap()).is_none());
+        assert!(keymap.bind("C-c", "no-such-command").is_err());
+    }
+
+    #[test]
+    fn default_keymaps_bind_the_usual_keys() {
+        let keymaps = Keymaps::default();
+        assert_eq!(command(&keymaps.global, "C-x C-s"), Some("save-buffer"));
+        assert_eq!(command(&keymaps.global, "C-x 4 b"), Some("switch-to-buffer-other-window"));
+        assert_eq!(command(&keymaps.global, "M-g g"), Some("goto-line"));
+        assert_eq!(command(&keymaps.isearch, "C-s"), Some("isearch-repeat-forward"));
+    }
+
+    #[test]
+    fn bindings_are_loaded_from_a_config() {
+        let mut keymaps = Keymaps::default();
+        let config = "# Mine\n\nglobal-set-key C-c g  goto-line\n  global-unset-key C-z\ndefine-key isearch C-o isearch-exit\n";
+        keymaps.load(config).unwrap();
+        assert_eq!(command(&keymaps.global, "C-c g"), Some("goto-line"));
+        assert_eq!(command(&keymaps.global, "C-z"), None);
+        assert_eq!(command(&keymaps.isearch, "C-o"), Some("isearch-exit"));
+
+        assert_eq!(keymaps.load("\nglobal-set-key C-c x nope"), Err("line 2: No command named nope".to_string()));
+        assert_eq!(keymaps.load("define-key nope C-a undo"), Err("line 1: No keymap named nope".to_string()));
+        assert_eq!(keymaps.load("global-set-key undo"), Err("line 1: global-set-key needs keys and a command".to_string()));
+        assert_eq!(keymaps.load("bind C-a undo"), Err("line 1: Unknown command bind".to_string()));
+    }
+}
@@ -0,0 +1,104 @@
+/// Maximum number of entries kept in the kill ring.
+const KILL_RING_MAX: usize = 60;
+
+/// Killed text, newest last.
+#[derive(Default)]
+pub struct KillRing {
+    entries: Vec<String>,
+    yank_idx: usize,
+}
+
+impl KillRing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `text` as a new entry.
+    pub fn push(&mut self, text: String) {
+        if self.entries.len() == KILL_RING_MAX {
+            self.entries.remove(0);
+        }
+        self.entries.push(text);
+        self.yank_idx = 0;
+    }
+
+    /// Adds `text` to the end of the newest entry.
+    pub fn append(&mut self, text: &str) {
+        match self.entries.last_mut() {
+            Some(last) => last.push_str(text),
+            None => self.push(text.to_string()),
+        }
+        self.yank_idx = 0;
+    }
+
+    /// Adds `text` to the start of the newest entry.
+    pub fn prepend(&mut self, text: &str) {
+        match self.entries.last_mut() {
+            Some(last) => last.insert_str(0, text),
+            None => self.push(text.to_string()),
+        }
+        self.yank_idx = 0;
+    }
+
+    /// Returns the newest entry.
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yank_idx = 0;
+        self.entries.last().map(String::as_str)
+    }
+
+    /// Moves to the next older entry, wrapping around, and returns it.
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.yank_idx = (self.yank_idx + 1) % self.entries.len();
+        let idx = self.entries.len() - 1 - self.yank_idx;
+        Some(&self.entries[idx])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yank_pop_rotates_through_older_kills() {
+        let mut ring = KillRing::new();
+        assert_eq!(ring.yank(), None);
+        assert_eq!(ring.rotate(), None);
+        for text in ["one", "two", "three"] {
+            ring.push(text.to_string());
+        }
+        assert_eq!(ring.yank(), Some("three"));
+        assert_eq!(ring.rotate(), Some("two"));
+        assert_eq!(ring.rotate(), Some("one"));
+        assert_eq!(ring.rotate(), Some("three"));
+        ring.rotate();
+        assert_eq!(ring.yank(), Some("three"));
+    }
+
+    #[test]
+    fn appending_kills_extend_the_newest_entry() {
+        let mut ring = KillRing::new();
+        ring.append("b");
+        ring.append("c");
+        ring.prepend("a");
+        assert_eq!(ring.yank(), Some("abc"));
+        ring.push("d".to_string());
+        assert_eq!(ring.rotate(), Some("abc"));
+    }
+
+    #[test]
+    fn the_oldest_kill_is_dropped_when_full() {
+        let mut ring = KillRing::new();
+        for i in 0..=KILL_RING_MAX {
+            ring.push(i.to_string());
+        }
+        ring.yank();
+        for _ in 2..KILL_RING_MAX {
+            ring.rotate();
+        }
+        assert_eq!(ring.rotate(), Some("1"));
+        assert_eq!(ring.rotate(), Some(KILL_RING_MAX.to_string().as_str()));
+    }
+}
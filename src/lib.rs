@@ -0,0 +1,46 @@
+//! The remacs editing engine, for embedding an Emacs-like editor in other
+//! programs. The `re` binary is a thin wrapper around it.
+//!
+//! An [`Editor`](editor::Editor) draws into a [`Screen`](screen::Screen)
+//! and shows it through a [`Backend`](backend::Backend), so it does not
+//! have to own the terminal. Key events go in through
+//! [`input::handle_event`], which runs the commands they are bound to in
+//! the editor's [`keymaps`](keymap::Keymaps):
+//!
+//! ```
+//! use remacs::backend::MemoryBackend;
+//! use remacs::crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+//! use remacs::editor::Editor;
+//! use remacs::input::handle_event;
+//!
+//! let mut editor = Editor::new();
+//! editor.backend = Box::new(MemoryBackend::new(40, 5));
+//! editor.keymaps.global.bind("C-c s", "save-buffer").unwrap();
+//! for c in "hi".chars() {
+//!     handle_event(&mut editor, Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)));
+//! }
+//! editor.render().unwrap();
+//! assert_eq!(editor.text().to_string(), "hi");
+//! assert_eq!(editor.screen.row_text(0), "hi");
+//! ```
+
+pub mod backend;
+pub mod buffer;
+pub mod commands;
+pub mod diff;
+pub mod display;
+pub mod editor;
+pub mod encoding;
+pub mod input;
+pub mod keymap;
+pub mod killring;
+pub mod screen;
+pub mod search;
+pub mod statusline;
+pub mod text;
+pub mod undo;
+pub mod window;
+#[cfg(test)]
+mod tests;
+
+pub use crossterm;
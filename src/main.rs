@@ -1,27 +1,82 @@
-mod editor;
-mod input;
-mod buffer;
-mod statusline;
-#[macro_use]
-mod macros;
+mod args;
 
-use crate::editor::Editor;
+use crate::args::{parse_args, USAGE};
+use remacs::buffer;
+use remacs::editor::{Editor, VERSION};
 
-use std::io;
+use std::io::{self, IsTerminal};
 
 fn main() -> io::Result<()> {
-    let args: Vec<String> = std::env::args().collect();
-    let mut editor = if args.len() > 1 {
-        let filename = &args[1];
-        if std::path::Path::new(filename).exists() {
-            let buf = buffer::load_buffer(filename)?;
-            Editor::from_file(filename.to_string(), buf)
-        } else {
-            Editor::new_with_filename(filename.to_string())
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("re: {e}");
+            eprintln!("Try 're --help' for more information.");
+            std::process::exit(2);
         }
+    };
+
+    if args.help {
+        println!("{USAGE}");
+        return Ok(());
+    }
+    if args.version {
+        println!("remacs {VERSION}");
+        return Ok(());
+    }
+
+    let read_stdin = args.stdin || (args.files.is_empty() && !io::stdin().is_terminal());
+    let mut editor = if read_stdin {
+        let (text, format) = buffer::load_stdin()?;
+        Editor::from_stdin(text, format)
     } else {
         Editor::new()
     };
-    
-    editor.start()
+
+    editor.backup = args.backup;
+    load_key_bindings(&mut editor);
+
+    for file in &args.files {
+        // A file that cannot be read leaves another buffer current
+        if !editor.find_file(&file.name) {
+            continue;
+        }
+        if args.read_only {
+            editor.buffer_mut().read_only = true;
+        }
+        if let Some((line, col)) = file.position {
+            editor.goto_line(line, col);
+        }
+    }
+    // Files opened take the place of the *scratch* buffer
+    if !read_stdin && editor.buffers.len() > 1 {
+        let message = editor.message.take();
+        editor.kill_buffer(0);
+        editor.message = message;
+    }
+    editor.switch_to_buffer(0);
+    editor.offer_recovery(0);
+
+    editor.start()?;
+    // The terminal is back to normal, so the last message can be shown
+    if let Some(message) = &editor.message {
+        eprintln!("{message}");
+    }
+    Ok(())
+}
+
+/// Makes the bindings in `~/.remacs`, if there is one, reporting the first
+/// line in error.
+fn load_key_bindings(editor: &mut Editor) {
+    let Some(home) = std::env::var_os("HOME") else { return };
+    let path = std::path::Path::new(&home).join(".remacs");
+    let result = match std::fs::read_to_string(&path) {
+        Ok(config) => editor.keymaps.load(&config),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = result {
+        editor.message = Some(format!("{}: {e}", path.display()));
+    }
 }
@@ -0,0 +1,159 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::display::grapheme_text;
+use crate::editor::TAB_WIDTH;
+
+/// One character cell of the screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cell {
+    /// The grapheme cluster shown, or an empty string for the second
+    /// column of a wide character.
+    pub symbol: String,
+    pub reverse: bool,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self { symbol: " ".to_string(), reverse: false }
+    }
+}
+
+/// What the terminal should show: a grid of cells and where the cursor is.
+/// Rendering fills one in and a backend puts it on the terminal.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Screen {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<Cell>,
+    pub cursor: (usize, usize),
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, cells: vec![Cell::default(); width * height], cursor: (0, 0) }
+    }
+
+    /// Blanks every cell, changing the size to `width` x `height`.
+    pub fn reset(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.cells.clear();
+        self.cells.resize(width * height, Cell::default());
+        self.cursor = (0, 0);
+    }
+
+    /// The cells of row `y`.
+    pub fn row(&self, y: usize) -> &[Cell] {
+        &self.cells[y * self.width..(y + 1) * self.width]
+    }
+
+    /// The text of row `y`, without attributes or trailing spaces.
+    pub fn row_text(&self, y: usize) -> String {
+        let row: String = self.row(y).iter().map(|cell| cell.symbol.as_str()).collect();
+        row.trim_end().to_string()
+    }
+
+    /// Writes `s` from column `x` of row `y`, cut off at the right edge,
+    /// and returns the columns it took. Control characters are shown as
+    /// the buffer shows them, a wide character that does not fit leaves a
+    /// blank, and a zero-width one joins the cell before it.
+    pub fn put_str(&mut self, x: usize, y: usize, s: &str, reverse: bool) -> usize {
+        if y >= self.height {
+            return 0;
+        }
+        let mut col = x;
+        for g in s.graphemes(true) {
+            let shown = grapheme_text(g, col - x, TAB_WIDTH);
+            for g in shown.graphemes(true) {
+                let width = g.width();
+                if width == 0 {
+                    if col > x {
+                        self.append(col - 1, y, g);
+                    }
+                    continue;
+                }
+                if col + width > self.width {
+                    while col < self.width {
+                        self.set(col, y, " ", reverse);
+                        col += 1;
+                    }
+                    return col - x;
+                }
+                self.set(col, y, g, reverse);
+                for pad in col + 1..col + width {
+                    self.set(pad, y, "", reverse);
+                }
+                col += width;
+            }
+        }
+        col - x
+    }
+
+    /// Fills the columns from `x` to `end` of row `y` with spaces.
+    pub fn fill(&mut self, x: usize, end: usize, y: usize, reverse: bool) {
+        for col in x..end.min(self.width) {
+            self.set(col, y, " ", reverse);
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, symbol: &str, reverse: bool) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let cell = &mut self.cells[y * self.width + x];
+        cell.symbol.clear();
+        cell.symbol.push_str(symbol);
+        cell.reverse = reverse;
+    }
+
+    /// Adds `symbol` to the character covering column `x` of row `y`.
+    fn append(&mut self, mut x: usize, y: usize, symbol: &str) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        while x > 0 && self.row(y)[x].symbol.is_empty() {
+            x -= 1;
+        }
+        self.cells[y * self.width + x].symbol.push_str(symbol);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn put_str_clips_and_pads_wide_characters() {
+        let mut screen = Screen::new(5, 2);
+        assert_eq!(screen.put_str(1, 0, "a中b", true), 4);
+        assert_eq!(screen.row(0)[2].symbol, "中");
+        assert_eq!(screen.row(0)[3].symbol, "");
+        assert!(screen.row(0)[4].reverse);
+        assert_eq!(screen.row_text(0), " a中b");
+
+        assert_eq!(screen.put_str(3, 1, "x中", false), 2);
+        assert_eq!(screen.row_text(1), "   x");
+    }
+
+    #[test]
+    fn put_str_shows_control_characters() {
+        let mut screen = Screen::new(10, 1);
+        screen.put_str(0, 0, "a\u{1}\tb", false);
+        assert_eq!(screen.row_text(0), "a^A b");
+    }
+
+    #[test]
+    fn zero_width_characters_join_the_cell_before() {
+        let mut screen = Screen::new(3, 1);
+        assert_eq!(screen.put_str(0, 0, "abc\u{200B}", false), 3);
+        assert_eq!(screen.row(0)[2].symbol, "c\u{200B}");
+
+        let mut screen = Screen::new(4, 2);
+        screen.put_str(0, 0, "\u{1}\u{301}中\u{301}", false);
+        assert_eq!(screen.row(0)[1].symbol, "A\u{301}");
+        assert_eq!(screen.row(0)[2].symbol, "中\u{301}");
+        screen.put_str(2, 1, "\u{301}", false);
+        assert_eq!(screen.row_text(1), "");
+    }
+}
@@ -0,0 +1,393 @@
+use regex::{Captures, Regex, RegexBuilder};
+
+use std::collections::VecDeque;
+
+use crate::text::Text;
+
+/// State of an incremental search in progress.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ISearch {
+    pub query: String,
+    pub forward: bool,
+    /// Point when the search started, restored on cancel.
+    pub origin: usize,
+    /// The current match as char indices.
+    pub found: Option<(usize, usize)>,
+    pub failing: bool,
+    pub wrapped: bool,
+    /// Position in the search history while browsing it with M-p/M-n.
+    pub history_idx: Option<usize>,
+}
+
+impl ISearch {
+    pub fn new(forward: bool, origin: usize) -> Self {
+        Self {
+            query: String::new(),
+            forward,
+            origin,
+            found: None,
+            failing: false,
+            wrapped: false,
+            history_idx: None,
+        }
+    }
+
+    pub fn prompt(&self) -> String {
+        format!(
+            "{}{}I-search{}: {}",
+            if self.failing { "Failing " } else { "" },
+            if self.wrapped { "Wrapping " } else { "" },
+            if self.forward { "" } else { " backward" },
+            self.query,
+        )
+    }
+
+    /// Where the cursor goes for the current match.
+    pub fn point(&self) -> usize {
+        match self.found {
+            Some((start, end)) => if self.forward { end } else { start },
+            None => self.origin,
+        }
+    }
+
+    /// Searches for the query from `from`, updating the match and the failing
+    /// state. A failed search keeps the previous match.
+    pub fn search_from(&mut self, buf: &Text, from: usize) {
+        if self.query.is_empty() {
+            self.found = None;
+            self.failing = false;
+            return;
+        }
+        let found = if self.forward {
+            find_forward(buf, &self.query, from)
+        } else {
+            find_backward(buf, &self.query, from)
+        };
+        self.failing = found.is_none();
+        if found.is_some() {
+            self.found = found;
+        }
+    }
+
+    /// Searches again after the query has changed, keeping the current match
+    /// if it still matches.
+    pub fn research(&mut self, buf: &Text) {
+        let from = match self.found {
+            Some((start, _)) if self.forward => start,
+            Some((start, _)) => start + self.query.chars().count(),
+            None => self.origin,
+        };
+        self.search_from(buf, from);
+    }
+
+    /// Moves to the next match in the search direction, wrapping around the
+    /// buffer if the search is already failing.
+    pub fn next(&mut self, buf: &Text) {
+        if self.failing {
+            self.wrapped = true;
+            let from = if self.forward { 0 } else { buf.len_chars() };
+            self.search_from(buf, from);
+            return;
+        }
+        let from = match self.found {
+            Some((start, _)) if self.forward => start + 1,
+            Some((_, end)) => end.saturating_sub(1),
+            None => self.origin,
+        };
+        self.search_from(buf, from);
+    }
+}
+
+/// Folds case for matching. A query with no uppercase letters matches
+/// case-insensitively.
+fn fold(c: char, case_fold: bool) -> char {
+    if case_fold {
+        c.to_lowercase().next().unwrap_or(c)
+    } else {
+        c
+    }
+}
+
+/// Whether `query` has no uppercase letters. In a regexp, a letter after a
+/// backslash is part of an escape such as `\W` and does not count.
+fn is_case_fold(query: &str, regexp: bool) -> bool {
+    let mut chars = query.chars();
+    while let Some(c) = chars.next() {
+        if regexp && c == '\\' {
+            chars.next();
+        } else if c.is_uppercase() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Finds the first match of `query` starting at or after `from`.
+pub fn find_forward(buf: &Text, query: &str, from: usize) -> Option<(usize, usize)> {
+    let case_fold = is_case_fold(query, false);
+    let query: Vec<char> = query.chars().map(|c| fold(c, case_fold)).collect();
+    let len = query.len();
+    if len == 0 || from > buf.len_chars() {
+        return None;
+    }
+
+    let mut window = VecDeque::with_capacity(len);
+    for (i, c) in buf.chars_at(from).enumerate() {
+        if window.len() == len {
+            window.pop_front();
+        }
+        window.push_back(fold(c, case_fold));
+        if window.len() == len && window.iter().eq(query.iter()) {
+            let end = from + i + 1;
+            return Some((end - len, end));
+        }
+    }
+    None
+}
+
+/// Finds the last match of `query` ending at or before `from`.
+pub fn find_backward(buf: &Text, query: &str, from: usize) -> Option<(usize, usize)> {
+    let case_fold = is_case_fold(query, false);
+    let query: Vec<char> = query.chars().rev().map(|c| fold(c, case_fold)).collect();
+    let len = query.len();
+    if len == 0 {
+        return None;
+    }
+    let from = from.min(buf.len_chars());
+
+    let mut window = VecDeque::with_capacity(len);
+    let mut chars = buf.chars_at(from);
+    let mut i = 0;
+    while let Some(c) = chars.prev() {
+        if window.len() == len {
+            window.pop_front();
+        }
+        window.push_back(fold(c, case_fold));
+        i += 1;
+        if window.len() == len && window.iter().eq(query.iter()) {
+            let start = from - i;
+            return Some((start, start + len));
+        }
+    }
+    None
+}
+
+/// A query-replace session over a snapshot of the buffer.
+///
+/// Matches are found in the text as it was when the session started, so
+/// replacement text is never matched again. `delta` tracks how far the
+/// buffer has shifted from the snapshot because of earlier replacements.
+#[derive(Debug)]
+pub struct Replace {
+    pub from: String,
+    pub to: String,
+    pub regexp: bool,
+    /// The current match in the buffer as char indices.
+    pub found: Option<(usize, usize)>,
+    pub replaced: usize,
+    regex: Regex,
+    snapshot: Text,
+    haystack: String,
+    /// Byte offset in `haystack` to search from next.
+    pos: usize,
+    /// Byte range in `haystack` of the current match.
+    current: Option<(usize, usize)>,
+    delta: isize,
+}
+
+impl PartialEq for Replace {
+    fn eq(&self, other: &Self) -> bool {
+        self.from == other.from
+            && self.to == other.to
+            && self.regexp == other.regexp
+            && self.found == other.found
+            && self.replaced == other.replaced
+    }
+}
+
+impl Replace {
+    /// Starts a session at `point`. Fails if `from` is not a valid regular
+    /// expression.
+    pub fn new(from: String, to: String, regexp: bool, buf: &Text, point: usize) -> Result<Self, regex::Error> {
+        let pattern = if regexp { from.clone() } else { regex::escape(&from) };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(is_case_fold(&from, regexp))
+            .multi_line(true)
+            .build()?;
+        Ok(Self {
+            from,
+            to,
+            regexp,
+            found: None,
+            replaced: 0,
+            regex,
+            snapshot: buf.clone(),
+            haystack: buf.to_string(),
+            pos: buf.char_to_byte(point),
+            current: None,
+            delta: 0,
+        })
+    }
+
+    pub fn prompt(&self) -> String {
+        format!(
+            "Query replacing {}{} with {}: (y, n, !, ., q) ",
+            if self.regexp { "regexp " } else { "" },
+            self.from,
+            self.to,
+        )
+    }
+
+    fn to_buffer(&self, byte: usize) -> usize {
+        (self.snapshot.byte_to_char(byte) as isize + self.delta) as usize
+    }
+
+    /// Moves to the next match and returns it, or `None` when there are no
+    /// more matches.
+    pub fn next_match(&mut self) -> Option<(usize, usize)> {
+        self.current = None;
+        self.found = None;
+        if self.pos > self.haystack.len() {
+            return None;
+        }
+        let m = self.regex.find_at(&self.haystack, self.pos)?;
+        self.pos = if m.is_empty() {
+            // Step over one char so an empty match is not found again
+            self.haystack[m.end()..].chars().next().map_or(usize::MAX, |c| m.end() + c.len_utf8())
+        } else {
+            m.end()
+        };
+        self.current = Some((m.start(), m.end()));
+        self.found = Some((self.to_buffer(m.start()), self.to_buffer(m.end())));
+        self.found
+    }
+
+    /// The text that replaces the current match. For regexp sessions `\N`
+    /// inserts capture group N and `\&` the whole match.
+    pub fn replacement(&self) -> String {
+        if !self.regexp {
+            return self.to.clone();
+        }
+        let Some((start, _)) = self.current else {
+            return self.to.clone();
+        };
+        match self.regex.captures_at(&self.haystack, start) {
+            Some(caps) => expand(&caps, &self.to),
+            None => self.to.clone(),
+        }
+    }
+
+    /// Records that the current match was replaced by `len` chars.
+    pub fn replaced_with(&mut self, len: usize) {
+        if let Some((start, end)) = self.found {
+            self.delta += len as isize - (end - start) as isize;
+            self.replaced += 1;
+        }
+    }
+}
+
+fn expand(caps: &Captures, template: &str) -> String {
+    let mut out = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('&') => out.push_str(&caps[0]),
+            Some(d) if d.is_ascii_digit() => {
+                let group = d.to_digit(10).unwrap() as usize;
+                out.push_str(caps.get(group).map_or("", |m| m.as_str()));
+            }
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_searches_find_the_nearest_match_either_way() {
+        let text = Text::from("one two\none two");
+        assert_eq!(find_forward(&text, "two", 0), Some((4, 7)));
+        assert_eq!(find_forward(&text, "two", 5), Some((12, 15)));
+        assert_eq!(find_forward(&text, "two", 13), None);
+        assert_eq!(find_forward(&text, "o\no", 0), Some((6, 9)));
+        assert_eq!(find_backward(&text, "two", 15), Some((12, 15)));
+        assert_eq!(find_backward(&text, "two", 14), Some((4, 7)));
+        assert_eq!(find_backward(&text, "one", 2), None);
+        assert_eq!(find_forward(&text, "", 0), None);
+    }
+
+    #[test]
+    fn lowercase_queries_ignore_case() {
+        let text = Text::from("Foo foo");
+        assert_eq!(find_forward(&text, "foo", 0), Some((0, 3)));
+        assert_eq!(find_forward(&text, "Foo", 1), None);
+        assert_eq!(find_backward(&text, "FOO", 7), None);
+        assert_eq!(find_backward(&text, "fOo", 7), None);
+        assert_eq!(find_backward(&text, "foo", 3), Some((0, 3)));
+    }
+
+    #[test]
+    fn isearch_wraps_after_failing() {
+        let text = Text::from("ab ab");
+        let mut search = ISearch::new(true, 1);
+        search.query.push_str("ab");
+        search.research(&text);
+        assert_eq!((search.found, search.point()), (Some((3, 5)), 5));
+        search.next(&text);
+        assert!(search.failing);
+        assert_eq!(search.found, Some((3, 5)));
+        search.next(&text);
+        assert!(search.wrapped && !search.failing);
+        assert_eq!(search.found, Some((0, 2)));
+        assert_eq!(search.prompt(), "Wrapping I-search: ab");
+    }
+
+    /// Replaces every match, returning the text and the replacement count.
+    fn replace_all(text: &str, from: &str, to: &str, regexp: bool) -> (String, usize) {
+        let mut text = Text::from(text);
+        let mut replace = Replace::new(from.to_string(), to.to_string(), regexp, &text, 0).unwrap();
+        while let Some((start, end)) = replace.next_match() {
+            let replacement = replace.replacement();
+            text.remove(start..end);
+            text.insert(start, &replacement);
+            replace.replaced_with(replacement.chars().count());
+        }
+        (text.to_string(), replace.replaced)
+    }
+
+    #[test]
+    fn replacements_shift_later_matches() {
+        assert_eq!(replace_all("a-a-a", "a", "xyz", false), ("xyz-xyz-xyz".into(), 3));
+        assert_eq!(replace_all("aaa bbb aaa", "aaa", "c", false), ("c bbb c".into(), 2));
+        // The replacement text is not matched again
+        assert_eq!(replace_all("ab", "a", "aa", false), ("aab".into(), 1));
+        assert_eq!(replace_all("a.c abc", ".", "!", false), ("a!c abc".into(), 1));
+    }
+
+    #[test]
+    fn regexp_replacements_expand_groups() {
+        assert_eq!(replace_all("ab cd", r"(\w)(\w)", r"\2\1", true), ("ba dc".into(), 2));
+        assert_eq!(replace_all("x y", r"\w", r"<\&>", true), ("<x> <y>".into(), 2));
+        assert_eq!(replace_all("ab", "(a)|(z)", r"[\2]\\", true), ("[]\\b".into(), 1));
+        assert_eq!(replace_all("ab", "a", r"\&", false), ("\\&b".into(), 1));
+        assert_eq!(replace_all("ab", "", "-", true), ("-a-b-".into(), 3));
+    }
+
+    #[test]
+    fn escapes_do_not_turn_off_case_folding() {
+        assert!(is_case_fold(r"foo\W\S", true));
+        assert!(!is_case_fold(r"\w Foo", true));
+        assert!(!is_case_fold(r"a\W", false));
+        assert_eq!(replace_all("FOO-bar", r"o\W", "_", true), ("FO_bar".into(), 1));
+        assert_eq!(replace_all("Foo foo", "foo", "x", false), ("x x".into(), 2));
+        assert_eq!(replace_all("Foo foo", "Foo", "x", false), ("x foo".into(), 1));
+    }
+}
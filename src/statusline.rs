@@ -1,40 +1,33 @@
-use crossterm::{
-    terminal::{self},
-    execute,
-    cursor,
-    style::{Attribute, SetAttribute},
-};
-use std::io::{self, stdout, Write};
 use std::path::Path;
 
+use crate::display::{str_width, truncate};
 use crate::editor::Editor;
 use crate::editor::VERSION;
+use crate::encoding::LineEnding;
+use crate::window::Window;
 
-pub fn create_statusline(editor: &mut Editor) -> io::Result<()> {
-    let mut stdout = stdout();
-
-    let (term_width, term_height) = terminal::size()?;
-    let term_height = term_height as usize;
+/// Builds the mode line for `win`, `width` columns wide.
+pub fn create_statusline(editor: &Editor, win: &Window, width: usize) -> String {
+    let buffer = &editor.buffers[win.buffer];
+    let mod_marker = match (buffer.read_only, buffer.modified) {
+        (true, _) => "%%",
+        (false, true) => "-*",
+        (false, false) => "--",
+    };
 
-    for (i, _line) in editor.buf.iter().enumerate() {
-        if i >= term_height - 2 { break; }
-    }
+    let filename = &buffer.name;
 
-    let mod_marker = if editor.modified { "-*" } else { "--" };
-    
-    let filename = if editor.filename.is_empty() {
-        "[No name]"
-    } else {
-        &editor.filename
+    let encoding = buffer.format.encoding;
+    let line_ending = match buffer.format.line_ending {
+        LineEnding::Lf => "",
+        LineEnding::CrLf => "(DOS)",
+        LineEnding::Cr => "(Mac)",
     };
 
-    // Make this detected later
-    let encoding = "utf-8";
-
-    let rel_path = if editor.filename.is_empty() {
+    let rel_path = if buffer.filename.is_empty() {
         "".to_string()
     } else {
-        let path = Path::new(&editor.filename);
+        let path = Path::new(&buffer.filename);
         match std::env::current_dir() {
             Ok(current_dir) => {
                 match path.strip_prefix(&current_dir) {
@@ -46,43 +39,39 @@ pub fn create_statusline(editor: &mut Editor) -> io::Result<()> {
         }
     };
 
-    let viewport_lines = term_height - 2;
-    let total_lines = editor.buf.len();
-    let last_visible_line = editor.row_offset + viewport_lines;
+    let viewport_lines = editor.visible_lines(win);
+    let total_lines = buffer.text.len_lines();
+    let last_visible_line = win.row_offset + viewport_lines;
 
-    let pos_marker = if total_lines <= viewport_lines {
+    // In visual-line mode the top line can be partly scrolled off
+    let at_top = win.row_offset == 0 && win.skip_rows == 0;
+    let pos_marker = if at_top && total_lines <= viewport_lines {
         "All"
-    } else if editor.row_offset == 0 {
+    } else if at_top {
         "Top"
     } else if last_visible_line >= total_lines {
         "Bot"
     } else {
-        let percent = ((editor.row_offset as f64 / (total_lines as f64 - viewport_lines as f64)) * 100.0).round() as usize;
+        let percent = ((win.row_offset as f64 / (total_lines as f64 - viewport_lines as f64)) * 100.0).round() as usize;
         &format!("{percent}%")
     };
 
     let left = format!(
-        "{} Remacs {}: {} ({}) {} ",
+        "{} Remacs {}: {} ({}){} {} ",
         mod_marker,
         VERSION,
         filename,
         encoding,
+        line_ending,
         rel_path,
     );
 
     let right = format!(" {} --", pos_marker);
-    let dash_count = term_width.saturating_sub((left.len() + right.len()).try_into().unwrap());
-    let filler = "-".repeat(dash_count.into());
+    let dash_count = width.saturating_sub(str_width(&left) + str_width(&right));
+    let filler = "-".repeat(dash_count);
 
     let statusline = format!("{}{}{}", left, filler, right);
-
-    execute!(
-        stdout, 
-        cursor::MoveTo(0, (term_height - 2) as u16),
-        SetAttribute(Attribute::Reverse),
-    )?;
-    write!(stdout, "{}", statusline)?;
-    execute!(stdout, SetAttribute(Attribute::NoReverse))?;
-
-    return Ok(());
+    let statusline = truncate(&statusline, width);
+    // Pad in case a wide character did not fit at the edge
+    format!("{statusline}{}", " ".repeat(width - str_width(statusline)))
 }
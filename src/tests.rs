@@ -0,0 +1,347 @@
+//! Runs the editor headless: events go in through `handle_event` and the
+//! screen it renders is checked cell by cell.
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+use crate::backend::MemoryBackend;
+use crate::editor::{Editor, EditorMode};
+use crate::input::handle_event;
+
+fn editor(width: usize, height: usize) -> Editor {
+    let mut editor = Editor::new();
+    editor.backend = Box::new(MemoryBackend::new(width, height));
+    editor
+}
+
+fn press(editor: &mut Editor, code: KeyCode, modifiers: KeyModifiers) {
+    handle_event(editor, Event::Key(KeyEvent::new(code, modifiers)));
+}
+
+fn ctrl(editor: &mut Editor, c: char) {
+    press(editor, KeyCode::Char(c), KeyModifiers::CONTROL);
+}
+
+fn type_str(editor: &mut Editor, s: &str) {
+    for c in s.chars() {
+        match c {
+            '\n' => press(editor, KeyCode::Enter, KeyModifiers::NONE),
+            '\t' => press(editor, KeyCode::Tab, KeyModifiers::NONE),
+            c => press(editor, KeyCode::Char(c), KeyModifiers::NONE),
+        }
+    }
+}
+
+/// Renders and returns the text of every screen row.
+fn rows(editor: &mut Editor) -> Vec<String> {
+    editor.render().unwrap();
+    (0..editor.screen.height).map(|y| editor.screen.row_text(y)).collect()
+}
+
+#[test]
+fn typed_text_is_shown_with_the_cursor_after_it() {
+    let mut e = editor(30, 5);
+    type_str(&mut e, "hello\nworld");
+    let rows = rows(&mut e);
+    assert_eq!(rows[0], "hello");
+    assert_eq!(rows[1], "world");
+    assert_eq!(rows[2], "~");
+    assert!(rows[3].starts_with("-* Remacs"), "{}", rows[3]);
+    assert!(e.screen.row(3).iter().all(|cell| cell.reverse));
+    assert_eq!(e.screen.cursor, (5, 1));
+    assert_eq!(e.text().to_string(), "hello\nworld");
+}
+
+#[test]
+fn moving_and_deleting_edit_the_buffer() {
+    let mut e = editor(30, 5);
+    type_str(&mut e, "abc\ndef");
+    ctrl(&mut e, 'p');
+    ctrl(&mut e, 'a');
+    ctrl(&mut e, 'd');
+    ctrl(&mut e, 'e');
+    type_str(&mut e, "!");
+    assert_eq!(e.text().to_string(), "bc!\ndef");
+    assert_eq!(rows(&mut e)[0], "bc!");
+    assert_eq!(e.screen.cursor, (3, 0));
+}
+
+#[test]
+fn messages_show_in_the_echo_area_once() {
+    let mut e = editor(30, 5);
+    ctrl(&mut e, 'g');
+    assert_eq!(rows(&mut e)[4], "Quit");
+    assert_eq!(rows(&mut e)[4], "");
+}
+
+#[test]
+fn the_region_is_shown_in_reverse_video_in_transient_mark_mode() {
+    let mut e = editor(30, 5);
+    type_str(&mut e, "abcdef");
+    ctrl(&mut e, 'a');
+    press(&mut e, KeyCode::Right, KeyModifiers::NONE);
+    ctrl(&mut e, ' ');
+    press(&mut e, KeyCode::Right, KeyModifiers::NONE);
+    press(&mut e, KeyCode::Right, KeyModifiers::NONE);
+    rows(&mut e);
+    let reversed: Vec<bool> = e.screen.row(0)[..6].iter().map(|cell| cell.reverse).collect();
+    assert_eq!(reversed, [false, true, true, false, false, false]);
+
+    ctrl(&mut e, 'x');
+    type_str(&mut e, "xm");
+    assert_eq!(rows(&mut e)[4], "Transient Mark mode disabled");
+    assert!(e.screen.row(0).iter().all(|cell| !cell.reverse));
+    ctrl(&mut e, 'w');
+    assert_eq!(e.text().to_string(), "adef");
+}
+
+#[test]
+fn split_windows_each_get_a_mode_line() {
+    let mut e = editor(20, 9);
+    type_str(&mut e, "one");
+    // Splitting needs the window laid out to know its size
+    rows(&mut e);
+    ctrl(&mut e, 'x');
+    type_str(&mut e, "2");
+    let rows = rows(&mut e);
+    assert_eq!(rows[0], "one");
+    assert!(rows[3].starts_with("-* Remacs"));
+    assert_eq!(rows[4], "one");
+    assert!(rows[7].starts_with("-* Remacs"));
+}
+
+#[test]
+fn tabs_and_wide_characters_take_their_display_width() {
+    let mut e = editor(30, 5);
+    e.buffer_mut().indent_tabs = true;
+    type_str(&mut e, "\tx\n中y");
+    let rows = rows(&mut e);
+    assert_eq!(rows[0], "    x");
+    assert_eq!(rows[1], "中y");
+    assert_eq!(e.screen.cursor, (3, 1));
+    assert_eq!(e.text().to_string(), "\tx\n中y");
+}
+
+#[test]
+fn long_lines_scroll_sideways_with_markers() {
+    let mut e = editor(10, 5);
+    type_str(&mut e, "0123456789abcdef");
+    let rows = rows(&mut e);
+    assert!(rows[0].starts_with('$'), "{}", rows[0]);
+    ctrl(&mut e, 'a');
+    let rows = self::rows(&mut e);
+    assert_eq!(rows[0], "012345678$");
+    assert_eq!(e.screen.cursor, (0, 0));
+}
+
+#[test]
+fn visual_line_mode_wraps_at_words() {
+    let mut e = editor(10, 6);
+    type_str(&mut e, "aaa bbb ccc ddd");
+    ctrl(&mut e, 'x');
+    type_str(&mut e, "xv");
+    let rows = rows(&mut e);
+    assert_eq!(&rows[..3], ["aaa bbb  \\", "ccc ddd", "~"]);
+    assert_eq!(e.screen.cursor, (7, 1));
+    ctrl(&mut e, 'p');
+    assert_eq!(e.cur_x, 7);
+}
+
+#[test]
+fn resizing_lays_the_windows_out_again() {
+    let mut e = editor(30, 5);
+    type_str(&mut e, "text");
+    assert!(rows(&mut e)[3].starts_with("-* Remacs"));
+    e.backend = Box::new(MemoryBackend::new(20, 8));
+    handle_event(&mut e, Event::Resize(20, 8));
+    let rows = rows(&mut e);
+    assert_eq!(rows.len(), 8);
+    assert!(rows[6].starts_with("-* Remacs"));
+    assert_eq!(e.screen.width, 20);
+}
+
+#[test]
+fn tiny_terminals_get_a_placeholder() {
+    let mut e = editor(5, 2);
+    type_str(&mut e, "text");
+    assert_eq!(rows(&mut e), ["Termi", ""]);
+    let mut e = editor(0, 0);
+    assert!(rows(&mut e).is_empty());
+}
+
+#[test]
+fn rebound_keys_run_their_new_command() {
+    let mut e = editor(30, 5);
+    type_str(&mut e, "ab");
+    e.keymaps.global.bind("C-c b", "move-beginning-of-line").unwrap();
+    ctrl(&mut e, 'c');
+    type_str(&mut e, "b!");
+    assert_eq!(e.text().to_string(), "!ab");
+
+    e.keymaps.global.unbind("C-a").unwrap();
+    ctrl(&mut e, 'a');
+    assert_eq!(rows(&mut e)[4], "C-a is undefined");
+}
+
+#[test]
+fn prefix_keys_wait_for_the_rest_of_the_sequence() {
+    let mut e = editor(30, 5);
+    ctrl(&mut e, 'x');
+    type_str(&mut e, "z");
+    assert_eq!(rows(&mut e)[4], "C-x z is undefined");
+    ctrl(&mut e, 'x');
+    ctrl(&mut e, 'g');
+    assert_eq!(rows(&mut e)[4], "Quit");
+    type_str(&mut e, "z");
+    assert_eq!(e.text().to_string(), "z");
+}
+
+#[test]
+fn goto_line_reads_a_number() {
+    let mut e = editor(30, 5);
+    type_str(&mut e, "one\ntwo\nthree");
+    press(&mut e, KeyCode::Char('g'), KeyModifiers::ALT);
+    type_str(&mut e, "g2");
+    assert_eq!(rows(&mut e)[4], "Goto line: 2");
+    type_str(&mut e, "\n");
+    assert_eq!((e.cur_x, e.cur_y), (0, 1));
+}
+
+#[test]
+fn other_keys_end_an_incremental_search_and_run() {
+    let mut e = editor(30, 5);
+    type_str(&mut e, "abc abc");
+    ctrl(&mut e, 'a');
+    ctrl(&mut e, 's');
+    type_str(&mut e, "bc");
+    ctrl(&mut e, 's');
+    ctrl(&mut e, 'e');
+    assert_eq!(e.mode, EditorMode::Normal);
+    assert_eq!(e.cur_x, 7);
+    ctrl(&mut e, 'x');
+    ctrl(&mut e, 'x');
+    assert_eq!(e.cur_x, 0);
+}
+
+#[test]
+fn query_replace_answers_come_from_its_keymap() {
+    let mut e = editor(30, 5);
+    type_str(&mut e, "a a a");
+    ctrl(&mut e, 'a');
+    press(&mut e, KeyCode::Char('%'), KeyModifiers::ALT);
+    type_str(&mut e, "a\nb\nyn!");
+    assert_eq!(e.text().to_string(), "b a b");
+    assert_eq!(rows(&mut e)[4], "Replaced 2 occurrences");
+}
+
+#[test]
+fn global_bindings_run_after_a_query_replace_but_not_at_prompts() {
+    let mut e = editor(30, 5);
+    e.keymaps.load("global-set-key C-c b backward-char").unwrap();
+    type_str(&mut e, "a a a");
+    ctrl(&mut e, 'a');
+    press(&mut e, KeyCode::Char('%'), KeyModifiers::ALT);
+    type_str(&mut e, "a\nb\ny");
+    // Ends the replacing, then runs
+    ctrl(&mut e, 'c');
+    type_str(&mut e, "b");
+    assert_eq!(e.mode, EditorMode::Normal);
+    assert_eq!((e.text().to_string().as_str(), e.cur_x), ("b a a", 2));
+
+    ctrl(&mut e, 'x');
+    ctrl(&mut e, 'w');
+    type_str(&mut e, "f");
+    ctrl(&mut e, 'a');
+    ctrl(&mut e, 'k');
+    assert_eq!(e.text().to_string(), "b a a");
+    assert_eq!(e.mode, EditorMode::SaveFile("f".to_string()));
+}
+
+#[test]
+fn yank_pop_after_point_moved_back_past_the_yank_does_not_panic() {
+    let mut e = editor(30, 5);
+    type_str(&mut e, "xxab");
+    ctrl(&mut e, 'b');
+    ctrl(&mut e, 'b');
+    ctrl(&mut e, 'k');
+    ctrl(&mut e, 'y');
+    // As a command run at a prompt could, without ending the yank
+    e.set_point(0);
+    press(&mut e, KeyCode::Char('y'), KeyModifiers::ALT);
+    assert_eq!(e.text().to_string(), "abxxab");
+}
+
+#[test]
+fn quick_exit_asks_for_a_file_name_for_unnamed_buffers() {
+    let mut e = editor(40, 5);
+    e.buffers = vec![crate::buffer::Buffer::from_text(
+        crate::editor::STDIN.to_string(),
+        "piped".into(),
+        Default::default(),
+    )];
+    press(&mut e, KeyCode::Char('z'), KeyModifiers::ALT);
+    assert_eq!(e.mode, EditorMode::SaveFile(String::new()));
+    let rows = rows(&mut e);
+    assert!(rows[4].starts_with("Write file:"), "{rows:?}");
+}
+
+#[test]
+fn killing_in_a_read_only_buffer_copies_the_text() {
+    let mut e = editor(50, 5);
+    type_str(&mut e, "one\ntwo");
+    e.buffer_mut().read_only = true;
+    ctrl(&mut e, 'p');
+    ctrl(&mut e, 'a');
+    ctrl(&mut e, 'k');
+    assert_eq!(rows(&mut e)[4], "Buffer is read-only, text copied to kill ring");
+    ctrl(&mut e, 'k');
+    assert_eq!(e.text().to_string(), "one\ntwo");
+    assert_eq!((e.cur_x, e.cur_y), (0, 1));
+    e.buffer_mut().read_only = false;
+    ctrl(&mut e, 'e');
+    ctrl(&mut e, 'y');
+    assert_eq!(e.text().to_string(), "one\ntwoone\n");
+}
+
+#[test]
+fn writing_over_another_file_asks_first_and_backs_it_up() {
+    let dir = std::env::temp_dir().join(format!("remacs-{}-write-file", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let (first, second) = (dir.join("first"), dir.join("second"));
+    std::fs::write(&first, "one\n").unwrap();
+    std::fs::write(&second, "two\n").unwrap();
+
+    let mut e = editor(80, 5);
+    e.backup = crate::buffer::Backup::Simple;
+    e.find_file(first.to_str().unwrap());
+    type_str(&mut e, "1");
+    ctrl(&mut e, 'x');
+    ctrl(&mut e, 's');
+    assert!(dir.join("first~").exists());
+
+    ctrl(&mut e, 'x');
+    ctrl(&mut e, 'w');
+    for _ in first.to_str().unwrap().chars() {
+        press(&mut e, KeyCode::Backspace, KeyModifiers::NONE);
+    }
+    type_str(&mut e, second.to_str().unwrap());
+    type_str(&mut e, "\n");
+    assert!(rows(&mut e)[4].ends_with("second exists; overwrite? (y/n)"));
+    type_str(&mut e, "y");
+    assert_eq!(std::fs::read_to_string(&second).unwrap(), "1one\n");
+    assert_eq!(std::fs::read_to_string(dir.join("second~")).unwrap(), "two\n");
+    assert_eq!(e.buffer().name, "second");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn a_partly_scrolled_wrapped_line_is_not_at_the_top() {
+    let mut e = editor(40, 5);
+    ctrl(&mut e, 'x');
+    type_str(&mut e, "xv");
+    type_str(&mut e, &"word ".repeat(48));
+    type_str(&mut e, "\nend");
+    // The wrapped first line starts above the window
+    let status = rows(&mut e)[3].clone();
+    assert!(status.contains(" Bot"), "{status}");
+}
@@ -0,0 +1,274 @@
+use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
+
+use std::ops::Range;
+
+use crate::display::grapheme_width;
+
+/// Text storage for a buffer.
+///
+/// Lines are separated by a single `\n` and the text always has at least one
+/// (possibly empty) line. Positions are either absolute char indices or
+/// `(line, col)` pairs where `col` is a char index into the line. All edits
+/// and lookups are O(log n) in the size of the text.
+#[derive(Debug, Clone, Default)]
+pub struct Text {
+    rope: Rope,
+}
+
+impl Text {
+    pub fn new() -> Self {
+        Self { rope: Rope::new() }
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    /// Returns the contents of line `idx` without its newline.
+    pub fn line(&self, idx: usize) -> String {
+        if idx >= self.len_lines() {
+            return String::new();
+        }
+        let start = self.rope.line_to_char(idx);
+        let end = start + self.line_len(idx);
+        self.rope.slice(start..end).to_string()
+    }
+
+    /// Length of line `idx` in chars, not counting the newline.
+    pub fn line_len(&self, idx: usize) -> usize {
+        if idx >= self.len_lines() {
+            return 0;
+        }
+        let line = self.rope.line(idx);
+        let len = line.len_chars();
+        if len > 0 && line.char(len - 1) == '\n' {
+            len - 1
+        } else {
+            len
+        }
+    }
+
+    pub fn line_to_char(&self, line: usize) -> usize {
+        self.rope.line_to_char(line.min(self.len_lines()))
+    }
+
+    pub fn char_to_line(&self, idx: usize) -> usize {
+        self.rope.char_to_line(idx.min(self.len_chars()))
+    }
+
+    pub fn char_to_byte(&self, idx: usize) -> usize {
+        self.rope.char_to_byte(idx.min(self.len_chars()))
+    }
+
+    pub fn byte_to_char(&self, idx: usize) -> usize {
+        self.rope.byte_to_char(idx.min(self.rope.len_bytes()))
+    }
+
+    /// Converts a `(line, col)` position to a char index, clamping `col` to
+    /// the end of the line.
+    pub fn pos_to_char(&self, line: usize, col: usize) -> usize {
+        let line = line.min(self.len_lines() - 1);
+        self.rope.line_to_char(line) + col.min(self.line_len(line))
+    }
+
+    pub fn char_to_pos(&self, idx: usize) -> (usize, usize) {
+        let idx = idx.min(self.len_chars());
+        let line = self.char_to_line(idx);
+        (line, idx - self.rope.line_to_char(line))
+    }
+
+    pub fn char(&self, idx: usize) -> Option<char> {
+        if idx < self.len_chars() {
+            Some(self.rope.char(idx))
+        } else {
+            None
+        }
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> String {
+        self.rope.slice(range).to_string()
+    }
+
+    /// Iterates over the chars starting at `idx`.
+    pub fn chars_at(&self, idx: usize) -> ropey::iter::Chars<'_> {
+        self.rope.chars_at(idx.min(self.len_chars()))
+    }
+
+    pub fn insert(&mut self, idx: usize, s: &str) {
+        self.rope.insert(idx, s);
+    }
+
+    /// Column of the start of the grapheme cluster before `col` on line
+    /// `idx`.
+    pub fn prev_grapheme(&self, idx: usize, col: usize) -> usize {
+        let mut start = 0;
+        for g in self.line(idx).graphemes(true) {
+            let end = start + g.chars().count();
+            if end >= col {
+                break;
+            }
+            start = end;
+        }
+        start
+    }
+
+    /// Column of the end of the grapheme cluster at `col` on line `idx`.
+    pub fn next_grapheme(&self, idx: usize, col: usize) -> usize {
+        let mut end = 0;
+        for g in self.line(idx).graphemes(true) {
+            end += g.chars().count();
+            if end > col {
+                break;
+            }
+        }
+        end
+    }
+
+    /// Screen columns taken by line `idx` up to `col`, with tab stops every
+    /// `tab_width` columns.
+    pub fn display_col(&self, idx: usize, col: usize, tab_width: usize) -> usize {
+        let mut start = 0;
+        let mut width = 0;
+        for g in self.line(idx).graphemes(true) {
+            start += g.chars().count();
+            if start > col {
+                break;
+            }
+            width += grapheme_width(g, width, tab_width);
+        }
+        width
+    }
+
+    /// The column on line `idx` of the grapheme cluster shown at screen
+    /// column `x`, or the end of the line if it is shorter.
+    pub fn col_at_display(&self, idx: usize, x: usize, tab_width: usize) -> usize {
+        let mut col = 0;
+        let mut width = 0;
+        for g in self.line(idx).graphemes(true) {
+            width += grapheme_width(g, width, tab_width);
+            if width > x {
+                break;
+            }
+            col += g.chars().count();
+        }
+        col
+    }
+
+    /// Where the screen rows of line `idx` start when it is wrapped to
+    /// `width` columns, as pairs of column and screen column. Rows break
+    /// after whitespace where they can.
+    pub fn wrap_line(&self, idx: usize, width: usize, tab_width: usize) -> Vec<(usize, usize)> {
+        let mut rows = vec![(0, 0)];
+        let (mut col, mut x) = (0, 0);
+        // Where the current row could end after whitespace
+        let mut break_at = None;
+        for g in self.line(idx).graphemes(true) {
+            let g_width = grapheme_width(g, x, tab_width);
+            let mut row_x = rows[rows.len() - 1].1;
+            while x + g_width > row_x + width && x > row_x {
+                let start = break_at.take().unwrap_or((col, x));
+                rows.push(start);
+                row_x = start.1;
+            }
+            col += g.chars().count();
+            x += g_width;
+            if g.chars().all(char::is_whitespace) {
+                break_at = Some((col, x));
+            }
+        }
+        rows
+    }
+
+    /// Removes the chars in `range` and returns them.
+    pub fn remove(&mut self, range: Range<usize>) -> String {
+        let removed = self.slice(range.clone());
+        self.rope.remove(range);
+        removed
+    }
+}
+
+impl From<&str> for Text {
+    fn from(s: &str) -> Self {
+        Self { rope: Rope::from_str(s) }
+    }
+}
+
+impl std::fmt::Display for Text {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for chunk in self.rope.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_split_on_newlines_without_them() {
+        let text = Text::from("one\ntwo\n");
+        assert_eq!(text.len_lines(), 3);
+        assert_eq!((text.line(0), text.line(1), text.line(2)), ("one".into(), "two".into(), "".into()));
+        assert_eq!((text.line_len(1), text.line_len(2), text.line_len(9)), (3, 0, 0));
+        assert_eq!(text.line(9), "");
+        assert_eq!(Text::new().len_lines(), 1);
+    }
+
+    #[test]
+    fn positions_convert_and_clamp() {
+        let text = Text::from("ab\ncde");
+        assert_eq!(text.pos_to_char(1, 2), 5);
+        assert_eq!(text.pos_to_char(0, 9), 2);
+        assert_eq!(text.pos_to_char(9, 0), 3);
+        assert_eq!(text.char_to_pos(5), (1, 2));
+        assert_eq!(text.char_to_pos(99), (1, 3));
+        assert_eq!(text.line_to_char(1), 3);
+        assert_eq!(text.char(2), Some('\n'));
+        assert_eq!(text.char(6), None);
+
+        let text = Text::from("é€x");
+        assert_eq!((text.char_to_byte(2), text.byte_to_char(5)), (5, 2));
+    }
+
+    #[test]
+    fn edits_insert_and_return_what_they_remove() {
+        let mut text = Text::from("hello world");
+        text.insert(5, ",\n");
+        assert_eq!(text.to_string(), "hello,\n world");
+        assert_eq!(text.remove(5..8), ",\n ");
+        assert_eq!(text.to_string(), "helloworld");
+        assert_eq!(text.chars_at(5).collect::<String>(), "world");
+        assert_eq!(text.slice(0..2), "he");
+    }
+
+    #[test]
+    fn columns_step_over_grapheme_clusters_and_wide_characters() {
+        // e + combining acute, a wide CJK character, then a tab
+        let text = Text::from("e\u{301}漢\tx");
+        assert_eq!((text.next_grapheme(0, 0), text.next_grapheme(0, 2)), (2, 3));
+        assert_eq!((text.prev_grapheme(0, 3), text.prev_grapheme(0, 2)), (2, 0));
+        assert_eq!(text.display_col(0, 2, 8), 1);
+        assert_eq!(text.display_col(0, 3, 8), 3);
+        assert_eq!(text.display_col(0, 4, 8), 8);
+        assert_eq!(text.col_at_display(0, 2, 8), 2);
+        assert_eq!(text.col_at_display(0, 5, 8), 3);
+        assert_eq!(text.col_at_display(0, 99, 8), 5);
+    }
+
+    #[test]
+    fn wrapped_rows_break_after_spaces_where_they_can() {
+        let text = Text::from("aaa bbb cccccccc");
+        assert_eq!(text.wrap_line(0, 8, 8), [(0, 0), (8, 8)]);
+        assert_eq!(text.wrap_line(0, 5, 8), [(0, 0), (4, 4), (8, 8), (13, 13)]);
+        assert_eq!(text.wrap_line(0, 20, 8), [(0, 0)]);
+        // A wide character does not straddle the edge
+        assert_eq!(Text::from("ab漢").wrap_line(0, 3, 8), [(0, 0), (2, 2)]);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::text::Text;
+
+/// Longest run of typed characters merged into a single undo step.
+const MAX_TYPING_RUN: usize = 20;
+
+/// A single buffer mutation, in char indices.
+#[derive(Debug, Clone)]
+pub enum Change {
+    Insert { at: usize, text: String },
+    Delete { at: usize, text: String },
+}
+
+impl Change {
+    fn apply(&self, buf: &mut Text) {
+        match self {
+            Change::Insert { at, text } => buf.insert(*at, text),
+            Change::Delete { at, text } => {
+                buf.remove(*at..*at + text.chars().count());
+            }
+        }
+    }
+
+    fn revert(&self, buf: &mut Text) {
+        match self {
+            Change::Insert { at, text } => {
+                buf.remove(*at..*at + text.chars().count());
+            }
+            Change::Delete { at, text } => buf.insert(*at, text),
+        }
+    }
+}
+
+/// The changes made by one command, undone and redone as a unit.
+struct Group {
+    changes: Vec<Change>,
+    point_before: usize,
+    point_after: usize,
+    seq: usize,
+    typing: bool,
+}
+
+/// Linear undo/redo history for a buffer.
+///
+/// Every change recorded between two calls to `boundary` goes into the same
+/// group. Typed characters from consecutive commands are merged as well, so
+/// a run of typing undoes in one step.
+#[derive(Default)]
+pub struct UndoLog {
+    undo: Vec<Group>,
+    redo: Vec<Group>,
+    seq: usize,
+    saved: Option<usize>,
+}
+
+impl UndoLog {
+    pub fn new() -> Self {
+        Self { saved: Some(0), ..Default::default() }
+    }
+
+    /// Starts a new command; later changes will not join the current group.
+    pub fn boundary(&mut self) {
+        self.seq += 1;
+    }
+
+    pub fn record(&mut self, change: Change, point_before: usize, point_after: usize, typing: bool) {
+        if !self.redo.is_empty() {
+            self.redo.clear();
+            if self.saved.is_some_and(|depth| depth > self.undo.len()) {
+                self.saved = None;
+            }
+        }
+
+        let at_saved = self.saved == Some(self.undo.len());
+        if let Some(group) = self.undo.last_mut()
+            && !at_saved
+            && (group.seq == self.seq
+                || (typing
+                    && group.typing
+                    && group.seq + 1 == self.seq
+                    && group.changes.len() < MAX_TYPING_RUN))
+        {
+            group.changes.push(change);
+            group.point_after = point_after;
+            group.seq = self.seq;
+            return;
+        }
+
+        self.undo.push(Group {
+            changes: vec![change],
+            point_before,
+            point_after,
+            seq: self.seq,
+            typing,
+        });
+    }
+
+    /// Reverts the most recent group and returns the point to restore, or
+    /// `None` if there is nothing to undo.
+    pub fn undo(&mut self, buf: &mut Text) -> Option<usize> {
+        let group = self.undo.pop()?;
+        for change in group.changes.iter().rev() {
+            change.revert(buf);
+        }
+        let point = group.point_before;
+        self.redo.push(group);
+        self.boundary();
+        Some(point)
+    }
+
+    /// Reapplies the most recently undone group and returns the point to
+    /// restore, or `None` if there is nothing to redo.
+    pub fn redo(&mut self, buf: &mut Text) -> Option<usize> {
+        let group = self.redo.pop()?;
+        for change in &group.changes {
+            change.apply(buf);
+        }
+        let point = group.point_after;
+        self.undo.push(group);
+        self.boundary();
+        Some(point)
+    }
+
+    /// Records the current state as the one on disk.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.undo.len());
+    }
+
+    /// Forgets the saved state, so the buffer counts as modified until it is
+    /// next saved.
+    pub fn mark_unsaved(&mut self) {
+        self.saved = None;
+    }
+
+    /// How many groups can be undone.
+    pub fn depth(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Whether the buffer matches the last saved state.
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.undo.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Types `s` at the end of `text`, one command per character.
+    fn type_str(log: &mut UndoLog, text: &mut Text, s: &str) {
+        for c in s.chars() {
+            log.boundary();
+            let at = text.len_chars();
+            text.insert(at, &c.to_string());
+            log.record(Change::Insert { at, text: c.to_string() }, at, at + 1, true);
+        }
+    }
+
+    #[test]
+    fn typing_is_undone_in_runs() {
+        let mut log = UndoLog::new();
+        let mut text = Text::new();
+        type_str(&mut log, &mut text, &"a".repeat(MAX_TYPING_RUN + 5));
+        assert_eq!(log.depth(), 2);
+        assert_eq!(log.undo(&mut text), Some(MAX_TYPING_RUN));
+        assert_eq!(text.len_chars(), MAX_TYPING_RUN);
+        assert_eq!(log.undo(&mut text), Some(0));
+        assert_eq!(log.undo(&mut text), None);
+        assert_eq!(log.redo(&mut text), Some(MAX_TYPING_RUN));
+        assert_eq!(text.len_chars(), MAX_TYPING_RUN);
+    }
+
+    #[test]
+    fn changes_within_a_command_are_grouped() {
+        let mut log = UndoLog::new();
+        let mut text = Text::from("abc");
+        log.boundary();
+        text.remove(0..1);
+        log.record(Change::Delete { at: 0, text: "a".into() }, 1, 0, false);
+        text.insert(0, "xy");
+        log.record(Change::Insert { at: 0, text: "xy".into() }, 0, 2, false);
+        type_str(&mut log, &mut text, "z");
+        assert_eq!(log.depth(), 2);
+        log.undo(&mut text);
+        assert_eq!(log.undo(&mut text), Some(1));
+        assert_eq!(text.to_string(), "abc");
+    }
+
+    #[test]
+    fn the_saved_state_is_tracked_through_undo_and_redo() {
+        let mut log = UndoLog::new();
+        let mut text = Text::new();
+        assert!(log.is_saved());
+        type_str(&mut log, &mut text, "ab");
+        log.mark_saved();
+        // Typing after a save starts a new group
+        type_str(&mut log, &mut text, "c");
+        assert_eq!(log.depth(), 2);
+        assert!(!log.is_saved());
+        log.undo(&mut text);
+        assert!(log.is_saved());
+        log.undo(&mut text);
+        assert!(!log.is_saved());
+
+        // The saved state cannot be reached again once redo is lost
+        type_str(&mut log, &mut text, "x");
+        log.undo(&mut text);
+        assert!(!log.is_saved());
+        log.mark_saved();
+        log.mark_unsaved();
+        assert!(!log.is_saved());
+    }
+}
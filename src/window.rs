@@ -0,0 +1,256 @@
+/// Smallest window height, in text lines, that C-x 2 will create.
+pub const WINDOW_MIN_HEIGHT: usize = 2;
+/// Smallest window width, in columns, that C-x 3 will create.
+pub const WINDOW_MIN_WIDTH: usize = 10;
+
+/// A view of a buffer with its own cursor and scroll position.
+///
+/// `top`, `left`, `width` and `height` give the text area on screen; the
+/// mode line is drawn on the row below it. They are recomputed from the
+/// layout on every redraw.
+#[derive(Debug, Clone, Default)]
+pub struct Window {
+    pub buffer: usize,
+    pub cur_x: usize,
+    pub cur_y: usize,
+    pub row_offset: usize,
+    /// In visual-line mode, the screen rows of line `row_offset` scrolled
+    /// off the top.
+    pub skip_rows: usize,
+    pub col_offset: usize,
+    /// How far C-x < and C-x > scrolled the window. Scrolling to follow
+    /// the cursor never goes further left than this.
+    pub min_col_offset: usize,
+    pub top: usize,
+    pub left: usize,
+    pub width: usize,
+    pub height: usize,
+    /// Whether a divider column is drawn to the right of the window.
+    pub divider: bool,
+}
+
+impl Window {
+    pub fn new(buffer: usize) -> Self {
+        Self { buffer, ..Default::default() }
+    }
+
+    /// Scrolls the window so its cursor, `x` columns into its line, is
+    /// visible. Scrolling sideways puts the cursor in the middle of the
+    /// window.
+    pub fn scroll_to_cursor(&mut self, x: usize) {
+        self.skip_rows = 0;
+        if self.cur_y < self.row_offset {
+            self.row_offset = self.cur_y;
+        } else if self.height > 0 && self.cur_y >= self.row_offset + self.height {
+            self.row_offset = self.cur_y - self.height + 1;
+        }
+
+        // The first column shows `$` once scrolled and the last one may
+        let left_marker = (self.col_offset > 0) as usize;
+        let usable = self.width.saturating_sub(1);
+        if x < self.col_offset + left_marker || x >= self.col_offset + usable {
+            self.col_offset = if x < self.min_col_offset + usable {
+                self.min_col_offset
+            } else {
+                x - self.width / 2
+            };
+        }
+    }
+
+    /// Scrolls a window whose lines wrap so its cursor, on screen row
+    /// `cur_row` of its line, is visible. Line `i` takes `rows(i)` screen
+    /// rows.
+    pub fn scroll_to_wrapped_cursor(&mut self, cur_row: usize, rows: impl Fn(usize) -> usize) {
+        self.col_offset = 0;
+        self.min_col_offset = 0;
+        if (self.cur_y, cur_row) < (self.row_offset, self.skip_rows) {
+            self.row_offset = self.cur_y;
+            self.skip_rows = cur_row;
+            return;
+        }
+
+        // Screen rows from the top of the window down to the cursor
+        let mut below = cur_row;
+        for line in self.row_offset..self.cur_y {
+            if below >= self.skip_rows + self.height {
+                break;
+            }
+            below += rows(line);
+        }
+        if below.saturating_sub(self.skip_rows) < self.height {
+            return;
+        }
+
+        // Put the cursor on the bottom row
+        let (mut line, mut skip) = (self.cur_y, cur_row);
+        let mut remaining = self.height.saturating_sub(1);
+        while remaining > 0 {
+            if skip > 0 {
+                let step = skip.min(remaining);
+                skip -= step;
+                remaining -= step;
+            } else if line > 0 {
+                line -= 1;
+                skip = rows(line) - 1;
+                remaining -= 1;
+            } else {
+                break;
+            }
+        }
+        self.row_offset = line;
+        self.skip_rows = skip;
+    }
+}
+
+/// How the screen is divided between windows. Leaves hold indices into the
+/// editor's window list, in the same order as that list.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Layout {
+    Window(usize),
+    Split {
+        /// Side by side (C-x 3) rather than one above the other (C-x 2).
+        vertical: bool,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    /// Gives every window its part of the `width` x `height` area at
+    /// `top`, `left`. Each window's height excludes its mode line.
+    pub fn arrange(&self, windows: &mut [Window], top: usize, left: usize, width: usize, height: usize) {
+        match self {
+            Layout::Window(idx) => {
+                let win = &mut windows[*idx];
+                win.top = top;
+                win.left = left;
+                win.width = width;
+                win.height = height.saturating_sub(1);
+                win.divider = false;
+            }
+            Layout::Split { vertical: false, first, second } => {
+                let first_height = height.div_ceil(2);
+                first.arrange(windows, top, left, width, first_height);
+                second.arrange(windows, top + first_height, left, width, height - first_height);
+            }
+            Layout::Split { vertical: true, first, second } => {
+                let first_width = width.div_ceil(2);
+                first.arrange(windows, top, left, first_width.saturating_sub(1), height);
+                first.set_divider(windows);
+                second.arrange(windows, top, left + first_width, width - first_width, height);
+            }
+        }
+    }
+
+    /// Marks the windows along the right edge of this layout as having a
+    /// divider.
+    fn set_divider(&self, windows: &mut [Window]) {
+        match self {
+            Layout::Window(idx) => windows[*idx].divider = true,
+            Layout::Split { vertical: false, first, second } => {
+                first.set_divider(windows);
+                second.set_divider(windows);
+            }
+            Layout::Split { vertical: true, second, .. } => second.set_divider(windows),
+        }
+    }
+
+    /// Splits window `target`, putting the new window `new` below or to the
+    /// right of it. Indices from `new` on are shifted up to make room.
+    pub fn split(&mut self, target: usize, new: usize, vertical: bool) {
+        self.renumber(|idx| if idx >= new { idx + 1 } else { idx });
+        self.split_leaf(target, new, vertical);
+    }
+
+    fn split_leaf(&mut self, target: usize, new: usize, vertical: bool) -> bool {
+        match self {
+            Layout::Window(idx) if *idx == target => {
+                *self = Layout::Split {
+                    vertical,
+                    first: Box::new(Layout::Window(target)),
+                    second: Box::new(Layout::Window(new)),
+                };
+                true
+            }
+            Layout::Window(_) => false,
+            Layout::Split { first, second, .. } => {
+                first.split_leaf(target, new, vertical) || second.split_leaf(target, new, vertical)
+            }
+        }
+    }
+
+    /// Removes window `target`, giving its space to its sibling. Indices
+    /// after `target` are shifted down.
+    pub fn remove(&mut self, target: usize) {
+        self.remove_leaf(target);
+        self.renumber(|idx| if idx > target { idx - 1 } else { idx });
+    }
+
+    fn remove_leaf(&mut self, target: usize) -> bool {
+        let Layout::Split { first, second, .. } = self else {
+            return false;
+        };
+        if **first == Layout::Window(target) {
+            *self = std::mem::replace(second, Layout::Window(0));
+            true
+        } else if **second == Layout::Window(target) {
+            *self = std::mem::replace(first, Layout::Window(0));
+            true
+        } else {
+            first.remove_leaf(target) || second.remove_leaf(target)
+        }
+    }
+
+    fn renumber(&mut self, f: impl Fn(usize) -> usize + Copy) {
+        match self {
+            Layout::Window(idx) => *idx = f(*idx),
+            Layout::Split { first, second, .. } => {
+                first.renumber(f);
+                second.renumber(f);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(win: &Window) -> (usize, usize, usize, usize, bool) {
+        (win.top, win.left, win.width, win.height, win.divider)
+    }
+
+    #[test]
+    fn splits_share_the_area_with_mode_lines_and_dividers() {
+        let mut layout = Layout::Window(0);
+        layout.split(0, 1, false);
+        layout.split(1, 2, true);
+        let mut windows = vec![Window::new(0); 3];
+        layout.arrange(&mut windows, 0, 0, 21, 11);
+        assert_eq!(area(&windows[0]), (0, 0, 21, 5, false));
+        assert_eq!(area(&windows[1]), (6, 0, 10, 4, true));
+        assert_eq!(area(&windows[2]), (6, 11, 10, 4, false));
+    }
+
+    #[test]
+    fn windows_are_renumbered_around_splits_and_removals() {
+        let mut layout = Layout::Window(0);
+        layout.split(0, 1, false);
+        // A new window 1 beside window 0 moves the old window 1 to 2
+        layout.split(0, 1, true);
+        let leaf = |idx| Box::new(Layout::Window(idx));
+        assert_eq!(
+            layout,
+            Layout::Split {
+                vertical: false,
+                first: Box::new(Layout::Split { vertical: true, first: leaf(0), second: leaf(1) }),
+                second: leaf(2),
+            }
+        );
+
+        layout.remove(0);
+        assert_eq!(layout, Layout::Split { vertical: false, first: leaf(0), second: leaf(1) });
+        layout.remove(1);
+        assert_eq!(layout, Layout::Window(0));
+    }
+}